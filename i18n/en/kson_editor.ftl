@@ -62,4 +62,102 @@ volume=Volume
 preview_offset=Preview Offset
 preview_duration=Preview Duration
 left=Left
-right=Right
\ No newline at end of file
+right=Right
+history=History
+initial_state=Initial state
+saved_state=Saved
+failed_to_open=Could not open chart
+failed_to_save=Could not save chart
+failed_to_export=Could not export chart
+failed_to_copy_audio=Could not copy audio file
+failed_to_play=Could not play audio
+invalid_audio_file=Invalid audio file path
+exported=Exported to {$path}
+edit_camera_value=Edit camera {$graph}
+failed_to_autosave=Could not autosave chart
+restore_unsaved_changes=Restore unsaved changes
+recover_unsaved_changes=Recover unsaved changes
+recovery_file_found=There are unsaved changes from a previous session that are newer than the chart on disk.
+restore=Restore
+discard=Discard
+backup_count=Backups kept per chart
+restore_backup=Restore backup
+failed_to_restore_backup=Could not restore backup
+no_backups=No backups
+select_all=Select All
+clear_selection=Clear Selection
+cut=Cut
+paste=Paste
+failed_to_paste=Could not paste
+snap=Snap
+snap_free=Free
+move_objects=Move Objects
+resize_note=Resize {$lane} note
+overlap_policy=Overlapping placements
+overlap_reject=Reject
+overlap_trim=Trim
+overlap_merge=Merge
+overlap_rejected=Not placed, it would overlap existing objects
+transform=Transform
+transform_selected_range=Selected range only
+mirror=Mirror
+shift=Shift
+scale=Scale
+shift_by=Shift by {$ticks} ticks
+scale_by=Scale by {$ratio}
+measures=Measures
+insert_measures_at_cursor=Insert at cursor
+delete_measures_at_cursor=Delete at cursor
+insert_measures=Insert {$count} measure(s)
+delete_measures=Delete {$count} measure(s)
+change_resolution=Change Resolution
+change_resolution_to=Change Resolution to {$resolution}
+current_resolution=Current resolution: {$resolution} ticks per beat
+new_resolution=New resolution
+resolution_exact=Every object fits the new resolution exactly
+resolution_inexact={$count} object(s) don't fit the new resolution and will be rounded
+select_inexact=Select Them
+apply_rounded=Apply and Round
+keep_real_time=Keep real time positions
+edit_bpm_change_keeping_time=Edit BPM Change Keeping Time
+retimed_off_grid={$count} object(s) moved off the snap grid and have been selected
+add_laser_point=Add {$side} Laser Point
+remove_laser_point=Remove {$side} Laser Point
+split_laser=Split {$side} Laser
+join_laser=Join {$side} Lasers
+move_laser_point=Move {$side} Laser Point
+toggle_laser_wide=Toggle wide laser
+inspector=Inspector
+nothing_to_inspect=Hover or select an object to inspect it
+position=Position
+invalid_position=Enter a position as measure:beat:tick
+length=Length (ticks)
+laser_section={$side} laser
+laser_point_count={$count} points
+wide_laser=Wide (2x)
+laser_point={$side} laser point
+value=Value
+slam=Slam
+slam_value=Slam value
+curve_a=Curve A
+curve_b=Curve B
+curve=Curve
+measure=Measure
+camera_at_cursor=Camera points at the cursor
+no_camera_points_at_cursor=None
+edit_laser_point=Edit {$side} Laser Point
+delete=Delete
+delete_objects=Delete Objects
+convert_to_long=Convert to Long Notes
+convert_to_chip=Convert to Chips
+change_lane=Change Lane
+edit_properties=Edit Properties
+add_bpm_change_here=Add BPM Change Here
+add_time_signature_change_here=Add Time Signature Change Here
+erase_objects=Erase {$count} object(s)
+position_taken=Another object is in the way at this position
+lock_layers=Lock Layers
+lasers=Lasers
+bpm_and_time_signatures=BPM and Time Signatures
+resolution_time_signatures=Some time signatures don't have a whole number of ticks per measure at this resolution
+audio_source_unavailable=The audio source is not available
\ No newline at end of file
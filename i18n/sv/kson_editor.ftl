@@ -62,4 +62,102 @@ volume=Volym
 preview_offset=Förhandsgranskningsförskjutning
 preview_duration=Förhandsgranskningslängd
 left=Vänster
-right=Höger
\ No newline at end of file
+right=Höger
+history=Historik
+initial_state=Ursprungligt tillstånd
+saved_state=Sparad
+failed_to_open=Kunde inte öppna diagrammet
+failed_to_save=Kunde inte spara diagrammet
+failed_to_export=Kunde inte exportera diagrammet
+failed_to_copy_audio=Kunde inte kopiera ljudfilen
+failed_to_play=Kunde inte spela upp ljud
+invalid_audio_file=Ogiltig sökväg till ljudfil
+exported=Exporterade till {$path}
+edit_camera_value=Justera kamera {$graph}
+failed_to_autosave=Kunde inte autospara diagrammet
+restore_unsaved_changes=Återställ osparade ändringar
+recover_unsaved_changes=Återställ osparade ändringar
+recovery_file_found=Det finns osparade ändringar från en tidigare session som är nyare än diagrammet på disken.
+restore=Återställ
+discard=Släng
+backup_count=Säkerhetskopior per diagram
+restore_backup=Återställ säkerhetskopia
+failed_to_restore_backup=Kunde inte återställa säkerhetskopian
+no_backups=Inga säkerhetskopior
+select_all=Markera allt
+clear_selection=Avmarkera
+cut=Klipp ut
+paste=Klistra in
+failed_to_paste=Kunde inte klistra in
+snap=Fäst
+snap_free=Fri
+move_objects=Flytta objekt
+resize_note=Ändra längd på {$lane} not
+overlap_policy=Överlappande placeringar
+overlap_reject=Avvisa
+overlap_trim=Korta av
+overlap_merge=Slå ihop
+overlap_rejected=Inte placerad, den skulle överlappa befintliga objekt
+transform=Transformera
+transform_selected_range=Endast markerat intervall
+mirror=Spegla
+shift=Flytta
+scale=Skala
+shift_by=Flytta {$ticks} ticks
+scale_by=Skala med {$ratio}
+measures=Takter
+insert_measures_at_cursor=Infoga vid markören
+delete_measures_at_cursor=Ta bort vid markören
+insert_measures=Infoga {$count} takt(er)
+delete_measures=Ta bort {$count} takt(er)
+change_resolution=Ändra upplösning
+change_resolution_to=Ändra upplösning till {$resolution}
+current_resolution=Nuvarande upplösning: {$resolution} ticks per slag
+new_resolution=Ny upplösning
+resolution_exact=Alla objekt passar den nya upplösningen exakt
+resolution_inexact={$count} objekt passar inte den nya upplösningen och kommer att avrundas
+select_inexact=Markera dem
+apply_rounded=Verkställ och avrunda
+keep_real_time=Behåll positioner i realtid
+edit_bpm_change_keeping_time=Ändra BPM-byte och behåll tid
+retimed_off_grid={$count} objekt hamnade utanför rutnätet och har markerats
+add_laser_point=Lägg till punkt på {$side} laser
+remove_laser_point=Ta bort punkt från {$side} laser
+split_laser=Dela {$side} laser
+join_laser=Slå ihop {$side} lasrar
+move_laser_point=Flytta punkt på {$side} laser
+toggle_laser_wide=Växla bred laser
+inspector=Inspektör
+nothing_to_inspect=Hovra över eller markera ett objekt för att inspektera det
+position=Position
+invalid_position=Ange en position som takt:slag:tick
+length=Längd (ticks)
+laser_section={$side} laser
+laser_point_count={$count} punkter
+wide_laser=Bred (2x)
+laser_point=Punkt på {$side} laser
+value=Värde
+slam=Slam
+slam_value=Slamvärde
+curve_a=Kurva A
+curve_b=Kurva B
+curve=Kurva
+measure=Takt
+camera_at_cursor=Kamerapunkter vid markören
+no_camera_points_at_cursor=Inga
+edit_laser_point=Redigera punkt på {$side} laser
+delete=Ta bort
+delete_objects=Ta bort objekt
+convert_to_long=Gör om till långa noter
+convert_to_chip=Gör om till korta noter
+change_lane=Byt fil
+edit_properties=Redigera egenskaper
+add_bpm_change_here=Lägg till BPM-ändring här
+add_time_signature_change_here=Lägg till taktartsändring här
+erase_objects=Sudda {$count} objekt
+position_taken=Ett annat objekt är i vägen på den här positionen
+lock_layers=Lås lager
+lasers=Lasrar
+bpm_and_time_signatures=BPM och taktarter
+resolution_time_signatures=Vissa taktarter får inte ett helt antal tick per takt med den här upplösningen
+audio_source_unavailable=Ljudkällan är inte tillgänglig
\ No newline at end of file
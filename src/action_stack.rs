@@ -1,62 +1,213 @@
-use crate::i18n;
+use std::collections::{HashMap, HashSet};
+
 use anyhow::Result;
 
-pub struct Action<T> {
+/// Number of actions between two cached snapshots of the edited value.
+/// Undoing replays at most this many actions from the closest snapshot.
+const CHECKPOINT_INTERVAL: usize = 16;
+
+/// A value that can be edited through an [`ActionStack`] by applying commands to it.
+pub trait Document: Clone {
+    type Command: Clone;
+
+    fn apply(&mut self, command: &Self::Command) -> Result<()>;
+
+    /// Combines two consecutive commands into one with the same effect as applying both.
+    fn merge_commands(first: Self::Command, second: Self::Command) -> Self::Command;
+}
+
+pub struct Action<T: Document> {
     id: u32,
+    parent: Option<u32>,
+    children: Vec<u32>,
+    /// The child that a redo from this action moves to.
+    redo_child: Option<u32>,
+    depth: usize,
     pub description: String,
-    pub action: Box<dyn Fn(&mut T) -> Result<()>>,
+    pub command: T::Command,
 }
 
-pub struct ActionStack<T: Clone> {
+impl<T: Document> Action<T> {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+/// Undo history stored as a tree. Undoing and then making a new edit starts a new branch
+/// instead of discarding the undone actions.
+///
+/// `None` is used as the id of the original value at the root of the tree.
+pub struct ActionStack<T: Document> {
     original: T,
-    undo_stack: Vec<Action<T>>,
-    redo_stack: Vec<Action<T>>,
+    /// Every action made since the last reset, indexed by id.
+    actions: Vec<Action<T>>,
+    root_children: Vec<u32>,
+    root_redo_child: Option<u32>,
+    /// The last applied action.
+    head: Option<u32>,
     saved: Option<u32>,
-    next_id: u32,
+    /// Values after every action at a depth that is a multiple of `CHECKPOINT_INTERVAL`.
+    checkpoints: HashMap<u32, T>,
+    /// The materialised value and the action it was materialised at.
+    current: Option<(Option<u32>, T)>,
+    /// The action that is still open for merging and the key it was made with.
+    merge_target: Option<(u32, String)>,
+    revision: u64,
 }
 
 impl<T> ActionStack<T>
 where
-    T: Clone,
+    T: Document,
 {
     pub fn new(original: T) -> Self {
         ActionStack {
+            current: Some((None, original.clone())),
             original,
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            actions: Vec::new(),
+            root_children: Vec::new(),
+            root_redo_child: None,
+            head: None,
             saved: None,
-            next_id: 0,
+            checkpoints: HashMap::new(),
+            merge_target: None,
+            revision: 0,
         }
     }
 
-    pub fn new_action(&mut self) -> &mut Action<T> {
-        self.undo_stack.push(Action {
-            action: Box::new(|_| panic!("Unset Action")),
-            description: String::new(),
-            id: self.next_id,
+    pub fn new_action(&mut self, description: impl Into<String>, command: T::Command) {
+        let id = self.actions.len() as u32;
+        let depth = self.head.map_or(1, |h| self.actions[h as usize].depth + 1);
+
+        self.actions.push(Action {
+            id,
+            parent: self.head,
+            children: Vec::new(),
+            redo_child: None,
+            depth,
+            description: description.into(),
+            command,
         });
-        self.next_id += 1;
-        self.redo_stack.clear();
-        self.undo_stack.last_mut().unwrap()
+
+        match self.head {
+            Some(parent) => self.actions[parent as usize].children.push(id),
+            None => self.root_children.push(id),
+        }
+        self.set_redo_child(id);
+
+        self.head = Some(id);
+        self.merge_target = None;
+        self.revision += 1;
+    }
+
+    /// Like [`Self::new_action`], but consecutive calls with the same `merge_key` are combined
+    /// into a single action that keeps the first description, until [`Self::seal`] is called
+    /// or another action is made.
+    pub fn new_merging_action(
+        &mut self,
+        merge_key: &str,
+        description: impl Into<String>,
+        command: T::Command,
+    ) {
+        let target = match (&self.merge_target, self.head) {
+            (Some((id, key)), Some(head)) if *id == head && key == merge_key => Some(head),
+            _ => None,
+        };
+
+        match target {
+            // Merging into a saved action or one that has been branched from would change
+            // the value they refer to.
+            Some(head)
+                if self.saved != Some(head) && self.actions[head as usize].children.is_empty() =>
+            {
+                // The command is tried on the value at the head before it is merged, so one
+                // that fails can't take the earlier edits of the gesture down with it. The
+                // cached value is updated in place so continuous edits don't replay history.
+                let at_head = self.current().map(|_| ());
+                let applied = at_head.and_then(|_| match &mut self.current {
+                    Some((_, value)) => value.apply(&command),
+                    None => Ok(()),
+                });
+
+                if applied.is_ok() {
+                    self.checkpoints.remove(&head);
+                    let action = &mut self.actions[head as usize];
+                    action.command = T::merge_commands(action.command.clone(), command);
+                    self.revision += 1;
+                } else {
+                    // A failed apply may have left the cached value half edited
+                    self.current = None;
+                    // Made a separate action, which is reported and discarded on its own when
+                    // it fails to apply, the gesture keeps merging into the last good state
+                    self.new_action(description, command);
+                    self.merge_target = Some((head, merge_key.to_string()));
+                }
+            }
+            _ => {
+                self.new_action(description, command);
+                self.merge_target = self.head.map(|head| (head, merge_key.to_string()));
+            }
+        }
+    }
+
+    /// Ends the current gesture, the next edit becomes a new action even if it has the
+    /// same merge key.
+    pub fn seal(&mut self) {
+        self.merge_target = None;
     }
 
     pub fn undo(&mut self) {
-        if let Some(action) = self.undo_stack.pop() {
-            self.redo_stack.push(action);
+        if let Some(head) = self.head {
+            self.set_redo_child(head);
+            self.head = self.actions[head as usize].parent;
+            self.revision += 1;
         }
     }
 
     pub fn redo(&mut self) {
-        if let Some(action) = self.redo_stack.pop() {
-            self.undo_stack.push(action);
+        if let Some(next) = self.next_in_branch(self.head) {
+            self.head = Some(next);
+            self.revision += 1;
+        }
+    }
+
+    /// Moves to the state right after the action with `id`, which may be on another branch.
+    /// `None` undoes everything.
+    pub fn jump_to(&mut self, id: Option<u32>) {
+        if id == self.head || matches!(id, Some(id) if id as usize >= self.actions.len()) {
+            return;
+        }
+
+        // Redo leads back towards the old head, except where the new path takes over.
+        for path in [self.head, id] {
+            let mut node = path;
+            while let Some(n) = node {
+                self.set_redo_child(n);
+                node = self.actions[n as usize].parent;
+            }
+        }
+
+        self.head = id;
+        self.revision += 1;
+    }
+
+    fn set_redo_child(&mut self, id: u32) {
+        match self.actions[id as usize].parent {
+            Some(parent) => self.actions[parent as usize].redo_child = Some(id),
+            None => self.root_redo_child = Some(id),
         }
     }
 
     pub fn reset(&mut self, origin: T) {
+        self.current = Some((None, origin.clone()));
         self.original = origin;
-        self.redo_stack.clear();
-        self.undo_stack.clear();
+        self.actions.clear();
+        self.root_children.clear();
+        self.root_redo_child = None;
+        self.head = None;
+        self.checkpoints.clear();
         self.saved = None;
+        self.merge_target = None;
+        self.revision += 1;
     }
 
     pub fn apply(&mut self) {
@@ -66,33 +217,316 @@ where
     }
 
     pub fn next_action_desc(&self) -> Option<String> {
-        self.redo_stack.last().map(|next| next.description.clone())
+        self.next_in_branch(self.head)
+            .map(|next| self.actions[next as usize].description.clone())
     }
 
     pub fn prev_action_desc(&self) -> Option<String> {
-        self.undo_stack.last().map(|next| next.description.clone())
+        self.head
+            .map(|head| self.actions[head as usize].description.clone())
     }
 
-    pub fn get_current(&mut self) -> Result<T> {
-        let mut current = self.original.clone();
-        for action in &self.undo_stack {
-            action.action.as_ref()(&mut current)?;
+    pub fn get(&self, id: u32) -> Option<&Action<T>> {
+        self.actions.get(id as usize)
+    }
+
+    /// Child actions of `id` in the order they were made.
+    pub fn children(&self, id: Option<u32>) -> &[u32] {
+        match id {
+            Some(id) => &self.actions[id as usize].children,
+            None => &self.root_children,
         }
-        Ok(current)
     }
 
-    pub fn save(&mut self) {
-        match self.undo_stack.last() {
-            Some(a) => self.saved = Some(a.id),
-            None => self.saved = None,
+    /// The child of `id` that a redo would move to.
+    pub fn next_in_branch(&self, id: Option<u32>) -> Option<u32> {
+        match id {
+            Some(id) => self.actions[id as usize].redo_child,
+            None => self.root_redo_child,
+        }
+    }
+
+    /// The last applied action, `None` when at the original value.
+    pub fn head(&self) -> Option<u32> {
+        self.head
+    }
+
+    /// Ids of every action that is part of the current value.
+    pub fn applied(&self) -> HashSet<u32> {
+        let mut applied = HashSet::new();
+        let mut node = self.head;
+        while let Some(n) = node {
+            applied.insert(n);
+            node = self.actions[n as usize].parent;
+        }
+        applied
+    }
+
+    /// Id of the last applied action when saved, `None` if saved at the original value.
+    pub fn saved_id(&self) -> Option<u32> {
+        self.saved
+    }
+
+    /// Increases every time the current value may have changed.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns the value with every action from the root to the head applied.
+    ///
+    /// The result is cached, so repeated calls without edits in between are free and
+    /// a single undo, redo or new action only replays a bounded number of actions.
+    ///
+    /// If an action fails to apply it is removed from the history together with every
+    /// action made after it, and the head moves back to the action before it.
+    pub fn current(&mut self) -> Result<&T> {
+        // Walk up from the head until reaching a node with a known value
+        let mut path = Vec::new();
+        let mut node = self.head;
+        let mut value = loop {
+            if matches!(&self.current, Some((at, _)) if *at == node) {
+                break self.current.take().unwrap().1;
+            }
+
+            match node {
+                Some(n) => {
+                    if let Some(checkpoint) = self.checkpoints.get(&n) {
+                        break checkpoint.clone();
+                    }
+                    path.push(n);
+                    node = self.actions[n as usize].parent;
+                }
+                None => break self.original.clone(),
+            }
+        };
+
+        for &n in path.iter().rev() {
+            let action = &self.actions[n as usize];
+            if let Err(e) = value.apply(&action.command) {
+                let description = action.description.clone();
+                self.discard(n);
+                return Err(e.context(format!("Could not apply \"{}\"", description)));
+            }
+            if action.depth % CHECKPOINT_INTERVAL == 0 {
+                self.checkpoints.insert(n, value.clone());
+            }
+        }
+
+        Ok(&self.current.insert((self.head, value)).1)
+    }
+
+    /// Detaches `id` and its descendants from the tree and moves the head to its parent.
+    fn discard(&mut self, id: u32) {
+        let parent = self.actions[id as usize].parent;
+        let (children, redo_child) = match parent {
+            Some(p) => {
+                let action = &mut self.actions[p as usize];
+                (&mut action.children, &mut action.redo_child)
+            }
+            None => (&mut self.root_children, &mut self.root_redo_child),
+        };
+        children.retain(|&c| c != id);
+        if *redo_child == Some(id) {
+            *redo_child = children.last().copied();
         }
+
+        self.head = parent;
+        self.revision += 1;
+    }
+
+    pub fn get_current(&mut self) -> Result<T> {
+        self.current().map(T::clone)
+    }
+
+    pub fn save(&mut self) {
+        self.saved = self.head;
     }
 
     pub fn saved(&self) -> bool {
-        match (self.undo_stack.last(), self.saved) {
-            (Some(a), Some(saved)) => a.id == saved,
-            (Some(_), None) => false,
-            _ => true,
+        self.saved == self.head
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+
+    /// A list of numbers, commands append numbers to it and fail on negative ones.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    struct Numbers(Vec<i32>);
+
+    impl Document for Numbers {
+        type Command = Vec<i32>;
+
+        fn apply(&mut self, command: &Vec<i32>) -> Result<()> {
+            for &n in command {
+                if n < 0 {
+                    bail!("Negative number");
+                }
+                self.0.push(n);
+            }
+            Ok(())
+        }
+
+        fn merge_commands(mut first: Vec<i32>, second: Vec<i32>) -> Vec<i32> {
+            first.extend(second);
+            first
+        }
+    }
+
+    fn current(stack: &mut ActionStack<Numbers>) -> Vec<i32> {
+        stack.current().unwrap().0.clone()
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut stack = ActionStack::new(Numbers::default());
+        stack.new_action("One", vec![1]);
+        stack.new_action("Two", vec![2]);
+        assert_eq!(current(&mut stack), vec![1, 2]);
+
+        stack.undo();
+        assert_eq!(current(&mut stack), vec![1]);
+        assert_eq!(stack.prev_action_desc().as_deref(), Some("One"));
+        assert_eq!(stack.next_action_desc().as_deref(), Some("Two"));
+
+        stack.undo();
+        stack.undo();
+        assert_eq!(current(&mut stack), Vec::<i32>::new());
+        assert_eq!(stack.head(), None);
+
+        stack.redo();
+        stack.redo();
+        stack.redo();
+        assert_eq!(current(&mut stack), vec![1, 2]);
+        assert_eq!(stack.next_action_desc(), None);
+    }
+
+    #[test]
+    fn new_action_after_undo_starts_a_branch() {
+        let mut stack = ActionStack::new(Numbers::default());
+        stack.new_action("One", vec![1]);
+        stack.new_action("Two", vec![2]);
+        stack.undo();
+        stack.new_action("Three", vec![3]);
+        assert_eq!(current(&mut stack), vec![1, 3]);
+        assert_eq!(stack.children(Some(0)), &[1, 2]);
+
+        stack.jump_to(Some(1));
+        assert_eq!(current(&mut stack), vec![1, 2]);
+        stack.undo();
+        stack.redo();
+        assert_eq!(current(&mut stack), vec![1, 2]);
+        let applied: HashSet<u32> = [0, 1].iter().copied().collect();
+        assert_eq!(stack.applied(), applied);
+    }
+
+    #[test]
+    fn replays_past_checkpoints() {
+        let mut stack = ActionStack::new(Numbers::default());
+        let count = CHECKPOINT_INTERVAL as i32 * 2 + 3;
+        for n in 0..count {
+            stack.new_action("Number", vec![n]);
+            current(&mut stack);
+        }
+        for _ in 0..CHECKPOINT_INTERVAL + 2 {
+            stack.undo();
         }
+
+        let expected: Vec<i32> = (0..count - CHECKPOINT_INTERVAL as i32 - 2).collect();
+        assert_eq!(current(&mut stack), expected);
+    }
+
+    #[test]
+    fn failing_action_is_discarded() {
+        let mut stack = ActionStack::new(Numbers::default());
+        stack.new_action("One", vec![1]);
+        stack.new_action("Negative", vec![2, -1]);
+
+        assert!(stack.current().is_err());
+        assert_eq!(current(&mut stack), vec![1]);
+        assert_eq!(stack.head(), Some(0));
+        assert!(stack.children(Some(0)).is_empty());
+        assert_eq!(stack.next_action_desc(), None);
+    }
+
+    #[test]
+    fn merging_actions() {
+        let mut stack = ActionStack::new(Numbers::default());
+        stack.new_merging_action("drag", "Drag", vec![1]);
+        stack.new_merging_action("drag", "Drag more", vec![2]);
+        stack.new_merging_action("drag", "Drag even more", vec![3]);
+
+        assert_eq!(current(&mut stack), vec![1, 2, 3]);
+        assert_eq!(stack.head(), Some(0));
+        assert_eq!(stack.prev_action_desc().as_deref(), Some("Drag"));
+
+        stack.undo();
+        assert_eq!(current(&mut stack), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn merging_needs_the_same_key() {
+        let mut stack = ActionStack::new(Numbers::default());
+        stack.new_merging_action("drag", "Drag", vec![1]);
+        stack.new_merging_action("resize", "Resize", vec![2]);
+        stack.new_merging_action("drag", "Drag", vec![3]);
+
+        assert_eq!(stack.head(), Some(2));
+        assert_eq!(current(&mut stack), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn seal_ends_merging() {
+        let mut stack = ActionStack::new(Numbers::default());
+        stack.new_merging_action("drag", "Drag", vec![1]);
+        stack.seal();
+        stack.new_merging_action("drag", "Drag", vec![2]);
+
+        assert_eq!(stack.head(), Some(1));
+        stack.undo();
+        assert_eq!(current(&mut stack), vec![1]);
+    }
+
+    #[test]
+    fn merging_stops_at_the_saved_action() {
+        let mut stack = ActionStack::new(Numbers::default());
+        stack.new_merging_action("drag", "Drag", vec![1]);
+        stack.save();
+        stack.new_merging_action("drag", "Drag", vec![2]);
+        assert_eq!(stack.head(), Some(1));
+        assert!(!stack.saved());
+
+        stack.undo();
+        assert!(stack.saved());
+        assert_eq!(current(&mut stack), vec![1]);
+    }
+
+    #[test]
+    fn failing_merge_keeps_the_earlier_edits() {
+        let mut stack = ActionStack::new(Numbers::default());
+        stack.new_merging_action("drag", "Drag", vec![1]);
+        stack.new_merging_action("drag", "Drag", vec![-1]);
+
+        assert!(stack.current().is_err());
+        assert_eq!(current(&mut stack), vec![1]);
+        assert_eq!(stack.head(), Some(0));
+
+        stack.new_merging_action("drag", "Drag", vec![2]);
+        assert_eq!(stack.head(), Some(0));
+        assert_eq!(stack.get(0).unwrap().command, vec![1, 2]);
+        assert_eq!(current(&mut stack), vec![1, 2]);
+    }
+
+    #[test]
+    fn apply_makes_the_current_value_the_original() {
+        let mut stack = ActionStack::new(Numbers::default());
+        stack.new_action("One", vec![1]);
+        stack.apply();
+
+        assert_eq!(stack.head(), None);
+        assert_eq!(stack.prev_action_desc(), None);
+        assert_eq!(current(&mut stack), vec![1]);
     }
 }
@@ -0,0 +1,144 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+
+use crate::utils;
+
+pub const DEFAULT_BACKUP_COUNT: usize = 5;
+const BACKUP_EXTENSION: &str = ".kson.bak";
+/// Length of timestamps formatted like `2022-05-31_18-30-00-123`, with milliseconds so
+/// saves within the same second get their own backup.
+const TIMESTAMP_LEN: usize = 23;
+/// Length of timestamps without milliseconds, written by earlier versions.
+const SECONDS_TIMESTAMP_LEN: usize = 19;
+
+/// A copy of a chart file as it was before it was overwritten by a save.
+pub struct Backup {
+    pub path: PathBuf,
+    /// UTC time of the save that replaced this version, sorts chronologically.
+    pub timestamp: String,
+}
+
+impl Backup {
+    pub fn label(&self) -> String {
+        let (date, time) = self.timestamp.split_at(10);
+        let (seconds, millis) = time[1..].split_at(8);
+        format!(
+            "{} {}{} UTC",
+            date,
+            seconds.replace('-', ":"),
+            millis.replace('-', ".")
+        )
+    }
+}
+
+/// Backups of `chart_path` are named `<file stem>.<timestamp>.kson.bak` and kept next to it.
+fn backup_prefix(chart_path: &Path) -> Result<String> {
+    chart_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .map(|s| format!("{}.", s))
+        .ok_or_else(|| anyhow!("Invalid chart path: {}", chart_path.display()))
+}
+
+/// Writes `chart` to `path`, first moving what was there into a new backup and removing
+/// the oldest backups beyond `keep`. With `keep` at 0 no backups are made, existing ones
+/// are left alone.
+pub fn save_chart(path: &Path, chart: &kson::Chart, keep: usize) -> Result<()> {
+    let data = serde_json::to_vec(chart)?;
+
+    if keep > 0 && path.exists() {
+        let backup_name = format!(
+            "{}{}{}",
+            backup_prefix(path)?,
+            timestamp_now(),
+            BACKUP_EXTENSION
+        );
+        fs::copy(path, path.with_file_name(backup_name))?;
+
+        for old in list(path)?.into_iter().skip(keep) {
+            fs::remove_file(old.path)?;
+        }
+    }
+
+    utils::write_atomic(path, &data)
+}
+
+/// Every backup of the chart at `chart_path`, newest first.
+pub fn list(chart_path: &Path) -> Result<Vec<Backup>> {
+    let prefix = backup_prefix(chart_path)?;
+    let dir = match chart_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let timestamp = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix(&prefix))
+            .and_then(|n| n.strip_suffix(BACKUP_EXTENSION))
+            .filter(|t| is_timestamp(t))
+            .map(String::from);
+
+        if let Some(timestamp) = timestamp {
+            backups.push(Backup { path, timestamp });
+        }
+    }
+
+    backups.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    Ok(backups)
+}
+
+/// Whether `text` is a timestamp written by [`timestamp_now`], with or without milliseconds.
+/// Other files that happen to be named like backups are never listed or removed.
+fn is_timestamp(text: &str) -> bool {
+    const PATTERN: &[u8] = b"0000-00-00_00-00-00-000";
+    let bytes = text.as_bytes();
+    (bytes.len() == TIMESTAMP_LEN || bytes.len() == SECONDS_TIMESTAMP_LEN)
+        && bytes.iter().zip(PATTERN).all(|(c, p)| match p {
+            b'0' => c.is_ascii_digit(),
+            _ => c == p,
+        })
+}
+
+fn timestamp_now() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    let secs = millis / 1000;
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs = secs % 86400;
+
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}-{:03}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+        millis % 1000
+    )
+}
+
+/// Converts days since the unix epoch to a (year, month, day) date.
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let doe = (z - era * 146097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe as i64 + era * 400;
+
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
@@ -209,6 +209,8 @@ impl CameraView {
             .map(|hsva| Hsva::new(hsva.h, 1.0, 1.0, 1.0))
             .map(Color32::from)
             .collect::<Vec<_>>();
+        // Wide sections get a bar across the track where they start
+        let mut wide_mesh = eframe::epaint::Mesh::with_texture(Default::default());
         for (side, lane) in chart.note.laser.iter().enumerate() {
             let mut laser_meshes = Vec::new();
 
@@ -216,6 +218,17 @@ impl CameraView {
                 screen
                     .draw_laser_section(section, &mut laser_meshes, mapped_color[side], true)
                     .unwrap();
+
+                if section.wide() == 2 {
+                    let (x, y) = screen.tick_to_pos(section.tick());
+                    let x = x + screen.track_width / 2.0;
+                    let h = Self::TRACK_LENGH / 200.0;
+                    let [r, g, b, _] = mapped_color[side].to_array();
+                    wide_mesh.add_colored_rect(
+                        rect_xy_wh([x, y, screen.track_width, h]),
+                        Color32::from_rgba_unmultiplied(r, g, b, 120),
+                    );
+                }
             }
             self.meshes.append(
                 &mut laser_meshes
@@ -227,6 +240,10 @@ impl CameraView {
                     .collect::<Vec<_>>(),
             )
         }
+        self.add_mesh(Mesh {
+            mesh: wide_mesh,
+            material: Material::Solid(BlendMode::Add),
+        });
     }
 
     pub fn add_track_overlay(&mut self) {
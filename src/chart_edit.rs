@@ -0,0 +1,1241 @@
+use crate::action_stack::Document;
+use crate::tools::CameraPaths;
+use crate::transform::{self, Transform};
+use crate::utils::Overlaps;
+use anyhow::{bail, Result};
+use kson::{BgmInfo, Chart, GraphPoint, GraphSectionPoint, Interval, LaserSection, MetaInfo};
+use serde::{Deserialize, Serialize};
+
+/// A single edit to a chart.
+///
+/// Tools describe their changes with these instead of closures so that edits can be
+/// inspected, logged and replayed outside of the editor.
+///
+/// Existing objects are referred to by their position (tick, lane or measure) rather than
+/// their index, applying an edit to an object that does not exist fails instead of
+/// modifying something else.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum ChartEdit {
+    AddInterval {
+        fx: bool,
+        lane: usize,
+        interval: Interval,
+    },
+    RemoveInterval {
+        fx: bool,
+        lane: usize,
+        y: u32,
+    },
+    /// Replaces the note starting at `y`, the new interval must not overlap its neighbours.
+    ResizeInterval {
+        fx: bool,
+        lane: usize,
+        y: u32,
+        interval: Interval,
+    },
+    AddLaserSection {
+        side: usize,
+        section: LaserSection,
+    },
+    RemoveLaserSection {
+        side: usize,
+        tick: u32,
+    },
+    /// Replaces the point at `ry` in the section starting at `section`.
+    SetLaserPoint {
+        side: usize,
+        section: u32,
+        ry: u32,
+        point: GraphSectionPoint,
+    },
+    /// Adds a point inside the section starting at `section`.
+    AddLaserPoint {
+        side: usize,
+        section: u32,
+        point: GraphSectionPoint,
+    },
+    /// Removes a point, the section must keep at least two points. Removing the first
+    /// point makes the section start at the next one.
+    RemoveLaserPoint {
+        side: usize,
+        section: u32,
+        ry: u32,
+    },
+    /// Removes the segment starting at the point at `ry`, leaving two sections.
+    SplitLaserSection {
+        side: usize,
+        section: u32,
+        ry: u32,
+    },
+    /// Connects the section starting at `section` to the next section on the same side.
+    JoinLaserSections {
+        side: usize,
+        section: u32,
+    },
+    /// Switches the section starting at `section` between the normal (1) and wide (2)
+    /// range, converting its values so it stays in the same place on screen.
+    SetLaserWide {
+        side: usize,
+        section: u32,
+        wide: u8,
+    },
+    AddBpm {
+        tick: u32,
+        bpm: f64,
+    },
+    EditBpm {
+        tick: u32,
+        bpm: f64,
+    },
+    RemoveBpm {
+        tick: u32,
+    },
+    AddTimeSig {
+        measure: u32,
+        time_sig: kson::TimeSignature,
+    },
+    EditTimeSig {
+        measure: u32,
+        time_sig: kson::TimeSignature,
+    },
+    RemoveTimeSig {
+        measure: u32,
+    },
+    SetMeta(MetaInfo),
+    SetBgm(BgmInfo),
+    AddCameraPoint {
+        graph: CameraPaths,
+        point: GraphPoint,
+    },
+    RemoveCameraPoint {
+        graph: CameraPaths,
+        y: u32,
+    },
+    SetCameraCurve {
+        graph: CameraPaths,
+        y: u32,
+        a: f64,
+        b: f64,
+    },
+    SetCameraValue {
+        graph: CameraPaths,
+        y: u32,
+        v: f64,
+    },
+    /// Mirrors, shifts or scales everything starting in `range`, or the whole chart.
+    Transform {
+        range: Option<(u32, u32)>,
+        transform: Transform,
+    },
+    /// Inserts empty measures before `measure`, moving everything after it.
+    InsertMeasures {
+        measure: u32,
+        count: u32,
+    },
+    /// Deletes measures and their contents, moving everything after them back.
+    DeleteMeasures {
+        measure: u32,
+        count: u32,
+    },
+    /// Adds or edits a BPM change, moving everything after it so it keeps its time.
+    SetBpmKeepingTime {
+        tick: u32,
+        bpm: f64,
+    },
+    /// Rescales every tick in the chart to a new resolution.
+    SetResolution {
+        resolution: u32,
+    },
+    /// Several edits applied in order as one action.
+    Batch(Vec<ChartEdit>),
+    /// Replaces the whole chart, used when restoring a recovery file.
+    ReplaceChart(Box<Chart>),
+}
+
+fn intervals_mut(chart: &mut Chart, fx: bool, lane: usize) -> Result<&mut Vec<Interval>> {
+    let lanes = if fx {
+        &mut chart.note.fx[..]
+    } else {
+        &mut chart.note.bt[..]
+    };
+
+    match lanes.get_mut(lane) {
+        Some(lane) => Ok(lane),
+        None => bail!("Lane {} does not exist", lane),
+    }
+}
+
+fn lasers_mut(chart: &mut Chart, side: usize) -> Result<&mut Vec<LaserSection>> {
+    match chart.note.laser.get_mut(side) {
+        Some(side) => Ok(side),
+        None => bail!("Laser side {} does not exist", side),
+    }
+}
+
+fn laser_section_mut(chart: &mut Chart, side: usize, tick: u32) -> Result<&mut LaserSection> {
+    let sections = lasers_mut(chart, side)?;
+    match sections.binary_search_by_key(&tick, |s| s.0) {
+        Ok(index) => Ok(&mut sections[index]),
+        Err(_) => bail!("There is no laser section at {}", tick),
+    }
+}
+
+pub fn camera_graph_mut(chart: &mut Chart, graph: CameraPaths) -> &mut Vec<GraphPoint> {
+    match graph {
+        CameraPaths::Zoom => &mut chart.camera.cam.body.zoom,
+        CameraPaths::RotationX => &mut chart.camera.cam.body.rotation_x,
+    }
+}
+
+impl Document for Chart {
+    type Command = ChartEdit;
+
+    fn apply(&mut self, command: &ChartEdit) -> Result<()> {
+        match command {
+            ChartEdit::AddInterval { fx, lane, interval } => {
+                let notes = intervals_mut(self, *fx, *lane)?;
+                let index = match notes.binary_search_by_key(&interval.y, |n| n.y) {
+                    Ok(_) => bail!("There already is a note at {} in lane {}", interval.y, lane),
+                    Err(index) => index,
+                };
+                let overlaps_neighbour = index
+                    .checked_sub(1)
+                    .into_iter()
+                    .chain(std::iter::once(index))
+                    .filter_map(|i| notes.get(i))
+                    .any(|n| n.overlaps(interval));
+                if overlaps_neighbour {
+                    bail!(
+                        "The note at {} in lane {} overlaps another note",
+                        interval.y,
+                        lane
+                    );
+                }
+                notes.insert(index, *interval);
+            }
+            ChartEdit::RemoveInterval { fx, lane, y } => {
+                let notes = intervals_mut(self, *fx, *lane)?;
+                match notes.binary_search_by_key(y, |n| n.y) {
+                    Ok(index) => notes.remove(index),
+                    Err(_) => bail!("There is no note at {} in lane {}", y, lane),
+                };
+            }
+            ChartEdit::ResizeInterval {
+                fx,
+                lane,
+                y,
+                interval,
+            } => {
+                let notes = intervals_mut(self, *fx, *lane)?;
+                let index = match notes.binary_search_by_key(y, |n| n.y) {
+                    Ok(index) => index,
+                    Err(_) => bail!("There is no note at {} in lane {}", y, lane),
+                };
+                // The note has to stay between its neighbours to keep the lane sorted
+                let after_previous = index
+                    .checked_sub(1)
+                    .map_or(true, |i| notes[i].y + notes[i].l < interval.y);
+                let before_next = notes
+                    .get(index + 1)
+                    .map_or(true, |next| interval.y + interval.l < next.y);
+                if !after_previous || !before_next {
+                    bail!(
+                        "The resized note at {} in lane {} overlaps another note",
+                        y,
+                        lane
+                    );
+                }
+                notes[index] = *interval;
+            }
+            ChartEdit::AddLaserSection { side, section } => {
+                let sections = lasers_mut(self, *side)?;
+                let index = match sections.binary_search_by_key(&section.0, |s| s.0) {
+                    Ok(_) => bail!("There already is a laser section at {}", section.0),
+                    Err(index) => index,
+                };
+                let overlaps_neighbour = index
+                    .checked_sub(1)
+                    .into_iter()
+                    .chain(std::iter::once(index))
+                    .filter_map(|i| sections.get(i))
+                    .any(|s| s.overlaps(section));
+                if overlaps_neighbour {
+                    bail!(
+                        "The laser section at {} overlaps another laser section",
+                        section.0
+                    );
+                }
+                sections.insert(index, section.clone());
+            }
+            ChartEdit::RemoveLaserSection { side, tick } => {
+                let sections = lasers_mut(self, *side)?;
+                match sections.binary_search_by_key(tick, |s| s.0) {
+                    Ok(index) => sections.remove(index),
+                    Err(_) => bail!("There is no laser section at {}", tick),
+                };
+            }
+            ChartEdit::SetLaserPoint {
+                side,
+                section,
+                ry,
+                point,
+            } => {
+                let section = laser_section_mut(self, *side, *section)?;
+                match section.1.binary_search_by_key(ry, |p| p.ry) {
+                    Ok(index) => section.1[index] = *point,
+                    Err(_) => bail!("There is no laser point at {} in the section", ry),
+                }
+            }
+            ChartEdit::AddLaserPoint {
+                side,
+                section,
+                point,
+            } => {
+                let section = laser_section_mut(self, *side, *section)?;
+                match section.1.binary_search_by_key(&point.ry, |p| p.ry) {
+                    Ok(_) => bail!(
+                        "There already is a laser point at {} in the section",
+                        point.ry
+                    ),
+                    Err(index) if index == section.1.len() => {
+                        bail!(
+                            "The laser point at {} is after the end of the section",
+                            point.ry
+                        )
+                    }
+                    Err(index) => section.1.insert(index, *point),
+                }
+            }
+            ChartEdit::RemoveLaserPoint { side, section, ry } => {
+                let section = laser_section_mut(self, *side, *section)?;
+                let index = match section.1.binary_search_by_key(ry, |p| p.ry) {
+                    Ok(index) => index,
+                    Err(_) => bail!("There is no laser point at {} in the section", ry),
+                };
+                if section.1.len() <= 2 {
+                    bail!("A laser section needs at least two points");
+                }
+                section.1.remove(index);
+
+                let start = section.1[0].ry;
+                section.0 += start;
+                for point in &mut section.1 {
+                    point.ry -= start;
+                }
+            }
+            ChartEdit::SplitLaserSection { side, section, ry } => {
+                let sections = lasers_mut(self, *side)?;
+                let index = match sections.binary_search_by_key(section, |s| s.0) {
+                    Ok(index) => index,
+                    Err(_) => bail!("There is no laser section at {}", section),
+                };
+                let points = &mut sections[index].1;
+                let split = match points.binary_search_by_key(ry, |p| p.ry) {
+                    Ok(split) if split >= 1 && split + 3 <= points.len() => split + 1,
+                    Ok(_) => bail!("Both parts of a split laser section need two points"),
+                    Err(_) => bail!("There is no laser point at {} in the section", ry),
+                };
+
+                let mut second = sections[index].clone();
+                second.1 = sections[index].1.split_off(split);
+                let start = second.1[0].ry;
+                second.0 += start;
+                for point in &mut second.1 {
+                    point.ry -= start;
+                }
+                sections.insert(index + 1, second);
+            }
+            ChartEdit::JoinLaserSections { side, section } => {
+                let sections = lasers_mut(self, *side)?;
+                let index = match sections.binary_search_by_key(section, |s| s.0) {
+                    Ok(index) if index + 1 < sections.len() => index,
+                    Ok(_) => bail!("There is no laser section after {}", section),
+                    Err(_) => bail!("There is no laser section at {}", section),
+                };
+
+                let next = sections.remove(index + 1);
+                let first = &mut sections[index];
+                let offset = next.0 - first.0;
+                first.1.extend(next.1.into_iter().map(|mut p| {
+                    p.ry += offset;
+                    p
+                }));
+            }
+            ChartEdit::SetLaserWide {
+                side,
+                section,
+                wide,
+            } => {
+                let section = laser_section_mut(self, *side, *section)?;
+                let convert: fn(f64) -> f64 = match (section.wide(), *wide) {
+                    (old, new) if old == new => return Ok(()),
+                    (1, 2) => |v| (v + 0.5) / 2.0,
+                    (2, 1) => |v| v * 2.0 - 0.5,
+                    (_, new) => bail!("Unsupported laser range {}", new),
+                };
+
+                let out_of_range = section
+                    .1
+                    .iter()
+                    .flat_map(|p| std::iter::once(p.v).chain(p.vf))
+                    .map(convert)
+                    .any(|v| !(0.0..=1.0).contains(&v));
+                if out_of_range {
+                    bail!("The laser section does not fit in the normal range");
+                }
+
+                for point in &mut section.1 {
+                    point.v = convert(point.v);
+                    point.vf = point.vf.map(convert);
+                }
+                section.2 = *wide;
+            }
+            ChartEdit::AddBpm { tick, bpm } => {
+                match self.beat.bpm.binary_search_by_key(tick, |c| c.0) {
+                    Ok(_) => bail!("There already is a BPM change at {}", tick),
+                    Err(index) => self.beat.bpm.insert(index, (*tick, *bpm)),
+                }
+            }
+            ChartEdit::EditBpm { tick, bpm } => {
+                match self.beat.bpm.binary_search_by_key(tick, |c| c.0) {
+                    Ok(index) => self.beat.bpm[index].1 = *bpm,
+                    Err(_) => bail!("There is no BPM change at {}", tick),
+                }
+            }
+            ChartEdit::RemoveBpm { tick } => {
+                match self.beat.bpm.binary_search_by_key(tick, |c| c.0) {
+                    Ok(index) => self.beat.bpm.remove(index),
+                    Err(_) => bail!("There is no BPM change at {}", tick),
+                };
+            }
+            ChartEdit::AddTimeSig { measure, time_sig } => {
+                match self.beat.time_sig.binary_search_by_key(measure, |c| c.0) {
+                    Ok(_) => bail!(
+                        "There already is a time signature change at measure {}",
+                        measure
+                    ),
+                    Err(index) => self.beat.time_sig.insert(index, (*measure, *time_sig)),
+                }
+            }
+            ChartEdit::EditTimeSig { measure, time_sig } => {
+                match self.beat.time_sig.binary_search_by_key(measure, |c| c.0) {
+                    Ok(index) => self.beat.time_sig[index].1 = *time_sig,
+                    Err(_) => bail!("There is no time signature change at measure {}", measure),
+                }
+            }
+            ChartEdit::RemoveTimeSig { measure } => {
+                match self.beat.time_sig.binary_search_by_key(measure, |c| c.0) {
+                    Ok(index) => self.beat.time_sig.remove(index),
+                    Err(_) => bail!("There is no time signature change at measure {}", measure),
+                };
+            }
+            ChartEdit::SetMeta(meta) => self.meta = meta.clone(),
+            ChartEdit::SetBgm(bgm) => self.audio.bgm = Some(bgm.clone()),
+            ChartEdit::AddCameraPoint { graph, point } => {
+                let graph = camera_graph_mut(self, *graph);
+                match graph.binary_search_by_key(&point.y, |p| p.y) {
+                    Ok(_) => bail!("There already is a camera point at {}", point.y),
+                    Err(index) => graph.insert(index, *point),
+                }
+            }
+            ChartEdit::RemoveCameraPoint { graph, y } => {
+                let graph = camera_graph_mut(self, *graph);
+                match graph.binary_search_by_key(y, |p| p.y) {
+                    Ok(index) => graph.remove(index),
+                    Err(_) => bail!("There is no camera point at {}", y),
+                };
+            }
+            ChartEdit::SetCameraCurve { graph, y, a, b } => {
+                let graph = camera_graph_mut(self, *graph);
+                match graph.binary_search_by_key(y, |p| p.y) {
+                    Ok(index) => {
+                        graph[index].a = Some(*a);
+                        graph[index].b = Some(*b);
+                    }
+                    Err(_) => bail!("There is no camera point at {}", y),
+                }
+            }
+            ChartEdit::SetCameraValue { graph, y, v } => {
+                let graph = camera_graph_mut(self, *graph);
+                match graph.binary_search_by_key(y, |p| p.y) {
+                    Ok(index) => graph[index].v = *v,
+                    Err(_) => bail!("There is no camera point at {}", y),
+                }
+            }
+            ChartEdit::Transform { range, transform } => {
+                transform::transform_chart(self, *range, *transform)?
+            }
+            ChartEdit::InsertMeasures { measure, count } => {
+                transform::insert_measures(self, *measure, *count)?
+            }
+            ChartEdit::DeleteMeasures { measure, count } => {
+                transform::delete_measures(self, *measure, *count)?
+            }
+            ChartEdit::SetBpmKeepingTime { tick, bpm } => {
+                transform::set_bpm_keeping_time(self, *tick, *bpm)?
+            }
+            ChartEdit::SetResolution { resolution } => {
+                transform::change_resolution(self, *resolution)?
+            }
+            ChartEdit::Batch(edits) => {
+                for edit in edits {
+                    self.apply(edit)?;
+                }
+            }
+            ChartEdit::ReplaceChart(chart) => *self = (**chart).clone(),
+        }
+
+        Ok(())
+    }
+
+    fn merge_commands(first: ChartEdit, second: ChartEdit) -> ChartEdit {
+        use ChartEdit::*;
+
+        // Edits that overwrite the same property make the first one redundant
+        match (first, second) {
+            (SetMeta(_), SetMeta(meta)) => SetMeta(meta),
+
+            (SetBgm(_), SetBgm(bgm)) => SetBgm(bgm),
+            (AddBpm { tick, .. }, EditBpm { tick: t, bpm }) if tick == t => AddBpm { tick, bpm },
+            (EditBpm { tick, .. }, EditBpm { tick: t, bpm }) if tick == t => EditBpm { tick, bpm },
+            (
+                AddTimeSig { measure, .. },
+                EditTimeSig {
+                    measure: m,
+                    time_sig,
+                },
+            ) if measure == m => AddTimeSig { measure, time_sig },
+            (
+                EditTimeSig { measure, .. },
+                EditTimeSig {
+                    measure: m,
+                    time_sig,
+                },
+            ) if measure == m => EditTimeSig { measure, time_sig },
+            (
+                SetLaserPoint {
+                    side, section, ry, ..
+                },
+                SetLaserPoint {
+                    side: s,
+                    section: sec,
+                    ry: r,
+                    point,
+                },
+            ) if side == s && section == sec && ry == r => SetLaserPoint {
+                side,
+                section,
+                ry,
+                point,
+            },
+            (
+                SetCameraCurve { graph, y, .. },
+                SetCameraCurve {
+                    graph: g,
+                    y: py,
+                    a,
+                    b,
+                },
+            ) if graph == g && y == py => SetCameraCurve { graph, y, a, b },
+            (SetCameraValue { graph, y, .. }, SetCameraValue { graph: g, y: py, v })
+                if graph == g && y == py =>
+            {
+                SetCameraValue { graph, y, v }
+            }
+            (AddCameraPoint { graph, point }, SetCameraValue { graph: g, y, v })
+                if graph == g && point.y == y =>
+            {
+                AddCameraPoint {
+                    graph,
+                    point: GraphPoint { v, ..point },
+                }
+            }
+            (
+                ResizeInterval {
+                    fx,
+                    lane,
+                    y,
+                    interval,
+                },
+                ResizeInterval {
+                    fx: f,
+                    lane: l,
+                    y: resized,
+                    interval: second,
+                },
+            ) if fx == f && lane == l && interval.y == resized => ResizeInterval {
+                fx,
+                lane,
+                y,
+                interval: second,
+            },
+            (
+                SetLaserWide { side, section, .. },
+                SetLaserWide {
+                    side: s,
+                    section: sec,
+                    wide,
+                },
+            ) if side == s && section == sec => SetLaserWide {
+                side,
+                section,
+                wide,
+            },
+            (Batch(mut edits), second) => {
+                edits.push(second);
+                Batch(edits)
+            }
+            (first, second) => Batch(vec![first, second]),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use kson::TimeSignature;
+
+    /// An empty 4/4 chart at 120 BPM, like a new chart in the editor.
+    pub(crate) fn chart() -> Chart {
+        let mut chart = Chart::new();
+        chart.beat.resolution = 240;
+        chart.beat.bpm.push((0, 120.0));
+        chart.beat.time_sig.push((0, TimeSignature(4, 4)));
+        chart
+    }
+
+    pub(crate) fn section(tick: u32, points: &[(u32, f64)]) -> LaserSection {
+        let points = points
+            .iter()
+            .map(|&(ry, v)| GraphSectionPoint {
+                ry,
+                v,
+                vf: None,
+                a: Some(0.5),
+                b: Some(0.5),
+            })
+            .collect();
+        LaserSection(tick, points, 1)
+    }
+
+    pub(crate) fn camera_point(y: u32, v: f64) -> GraphPoint {
+        GraphPoint {
+            y,
+            v,
+            vf: None,
+            a: Some(0.5),
+            b: Some(0.5),
+        }
+    }
+
+    pub(crate) fn notes(chart: &Chart, fx: bool, lane: usize) -> Vec<(u32, u32)> {
+        let lanes = if fx { &chart.note.fx } else { &chart.note.bt };
+        lanes[lane].iter().map(|n| (n.y, n.l)).collect()
+    }
+
+    pub(crate) fn sections(chart: &Chart, side: usize) -> Vec<(u32, Vec<(u32, f64)>)> {
+        chart.note.laser[side]
+            .iter()
+            .map(|s| (s.0, s.1.iter().map(|p| (p.ry, p.v)).collect()))
+            .collect()
+    }
+
+    pub(crate) fn time_sigs(chart: &Chart) -> Vec<(u32, u32, u32)> {
+        chart
+            .beat
+            .time_sig
+            .iter()
+            .map(|&(m, TimeSignature(n, d))| (m, n, d))
+            .collect()
+    }
+
+    fn add_note(chart: &mut Chart, lane: usize, y: u32, l: u32) -> Result<()> {
+        chart.apply(&ChartEdit::AddInterval {
+            fx: false,
+            lane,
+            interval: Interval { y, l },
+        })
+    }
+
+    fn resize(y: u32, new_y: u32, l: u32) -> ChartEdit {
+        ChartEdit::ResizeInterval {
+            fx: false,
+            lane: 0,
+            y,
+            interval: Interval { y: new_y, l },
+        }
+    }
+
+    #[test]
+    fn add_interval() {
+        let mut chart = chart();
+        add_note(&mut chart, 0, 240, 0).unwrap();
+        add_note(&mut chart, 0, 0, 120).unwrap();
+        chart
+            .apply(&ChartEdit::AddInterval {
+                fx: true,
+                lane: 1,
+                interval: Interval { y: 0, l: 480 },
+            })
+            .unwrap();
+
+        assert_eq!(notes(&chart, false, 0), vec![(0, 120), (240, 0)]);
+        assert_eq!(notes(&chart, true, 1), vec![(0, 480)]);
+    }
+
+    #[test]
+    fn add_interval_fails_on_overlap() {
+        let mut chart = chart();
+        add_note(&mut chart, 0, 240, 240).unwrap();
+
+        assert!(add_note(&mut chart, 0, 240, 0).is_err());
+        assert!(add_note(&mut chart, 0, 360, 0).is_err());
+        assert!(add_note(&mut chart, 0, 0, 240).is_err());
+        assert!(add_note(&mut chart, 0, 480, 0).is_err());
+        assert!(add_note(&mut chart, 4, 0, 0).is_err());
+        assert_eq!(notes(&chart, false, 0), vec![(240, 240)]);
+    }
+
+    #[test]
+    fn remove_interval() {
+        let mut chart = chart();
+        add_note(&mut chart, 2, 0, 0).unwrap();
+        let remove = ChartEdit::RemoveInterval {
+            fx: false,
+            lane: 2,
+            y: 0,
+        };
+
+        chart.apply(&remove).unwrap();
+        assert!(notes(&chart, false, 2).is_empty());
+        assert!(chart.apply(&remove).is_err());
+    }
+
+    #[test]
+    fn resize_interval() {
+        let mut chart = chart();
+        add_note(&mut chart, 0, 0, 100).unwrap();
+        add_note(&mut chart, 0, 200, 0).unwrap();
+        add_note(&mut chart, 0, 400, 0).unwrap();
+
+        chart.apply(&resize(200, 150, 200)).unwrap();
+        assert_eq!(
+            notes(&chart, false, 0),
+            vec![(0, 100), (150, 200), (400, 0)]
+        );
+    }
+
+    #[test]
+    fn resize_interval_stays_between_neighbours() {
+        let mut chart = chart();
+        add_note(&mut chart, 0, 0, 100).unwrap();
+        add_note(&mut chart, 0, 200, 0).unwrap();
+        add_note(&mut chart, 0, 400, 0).unwrap();
+
+        assert!(chart.apply(&resize(200, 200, 200)).is_err());
+        assert!(chart.apply(&resize(200, 100, 0)).is_err());
+        assert!(chart.apply(&resize(200, 500, 0)).is_err());
+        assert!(chart.apply(&resize(300, 300, 0)).is_err());
+        assert_eq!(notes(&chart, false, 0), vec![(0, 100), (200, 0), (400, 0)]);
+    }
+
+    #[test]
+    fn add_and_remove_laser_section() {
+        let mut chart = chart();
+        let first = section(0, &[(0, 0.0), (240, 1.0)]);
+        chart
+            .apply(&ChartEdit::AddLaserSection {
+                side: 1,
+                section: first.clone(),
+            })
+            .unwrap();
+
+        let add = |section: LaserSection| ChartEdit::AddLaserSection { side: 1, section };
+        assert!(chart.apply(&add(first)).is_err());
+        assert!(chart
+            .apply(&add(section(240, &[(0, 0.0), (240, 1.0)])))
+            .is_err());
+        assert!(chart
+            .apply(&ChartEdit::AddLaserSection {
+                side: 2,
+                section: section(480, &[(0, 0.0), (240, 1.0)]),
+            })
+            .is_err());
+        chart
+            .apply(&add(section(480, &[(0, 0.0), (240, 1.0)])))
+            .unwrap();
+        assert_eq!(
+            sections(&chart, 1),
+            vec![
+                (0, vec![(0, 0.0), (240, 1.0)]),
+                (480, vec![(0, 0.0), (240, 1.0)])
+            ]
+        );
+
+        let remove = ChartEdit::RemoveLaserSection { side: 1, tick: 0 };
+        chart.apply(&remove).unwrap();
+        assert!(chart.apply(&remove).is_err());
+        assert_eq!(sections(&chart, 1), vec![(480, vec![(0, 0.0), (240, 1.0)])]);
+    }
+
+    fn chart_with_section(points: &[(u32, f64)]) -> Chart {
+        let mut chart = chart();
+        chart.note.laser[0].push(section(240, points));
+        chart
+    }
+
+    #[test]
+    fn set_laser_point() {
+        let mut chart = chart_with_section(&[(0, 0.0), (240, 1.0)]);
+        let mut point = chart.note.laser[0][0].1[1];
+        point.v = 0.5;
+
+        chart
+            .apply(&ChartEdit::SetLaserPoint {
+                side: 0,
+                section: 240,
+                ry: 240,
+                point,
+            })
+            .unwrap();
+        assert_eq!(sections(&chart, 0), vec![(240, vec![(0, 0.0), (240, 0.5)])]);
+
+        assert!(chart
+            .apply(&ChartEdit::SetLaserPoint {
+                side: 0,
+                section: 240,
+                ry: 120,
+                point,
+            })
+            .is_err());
+        assert!(chart
+            .apply(&ChartEdit::SetLaserPoint {
+                side: 0,
+                section: 0,
+                ry: 240,
+                point,
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn add_laser_point() {
+        let mut chart = chart_with_section(&[(0, 0.0), (240, 1.0)]);
+        let add = |ry: u32| ChartEdit::AddLaserPoint {
+            side: 0,
+            section: 240,
+            point: section(0, &[(ry, 0.5)]).1[0],
+        };
+
+        chart.apply(&add(120)).unwrap();
+        assert_eq!(
+            sections(&chart, 0),
+            vec![(240, vec![(0, 0.0), (120, 0.5), (240, 1.0)])]
+        );
+        assert!(chart.apply(&add(120)).is_err());
+        assert!(chart.apply(&add(480)).is_err());
+    }
+
+    #[test]
+    fn remove_laser_point() {
+        let mut chart = chart_with_section(&[(0, 0.0), (120, 0.5), (240, 1.0)]);
+        let remove = |ry: u32, section: u32| ChartEdit::RemoveLaserPoint {
+            side: 0,
+            section,
+            ry,
+        };
+
+        assert!(chart.apply(&remove(60, 240)).is_err());
+        chart.apply(&remove(0, 240)).unwrap();
+        assert_eq!(sections(&chart, 0), vec![(360, vec![(0, 0.5), (120, 1.0)])]);
+        assert!(chart.apply(&remove(0, 360)).is_err());
+    }
+
+    #[test]
+    fn split_and_join_laser_section() {
+        let points = [(0, 0.0), (120, 0.25), (240, 0.5), (360, 1.0)];
+        let mut chart = chart_with_section(&points);
+        let split = |ry: u32| ChartEdit::SplitLaserSection {
+            side: 0,
+            section: 240,
+            ry,
+        };
+
+        assert!(chart.apply(&split(0)).is_err());
+        assert!(chart.apply(&split(240)).is_err());
+        assert!(chart.apply(&split(60)).is_err());
+        chart.apply(&split(120)).unwrap();
+        assert_eq!(
+            sections(&chart, 0),
+            vec![
+                (240, vec![(0, 0.0), (120, 0.25)]),
+                (480, vec![(0, 0.5), (120, 1.0)])
+            ]
+        );
+
+        let join = |section: u32| ChartEdit::JoinLaserSections { side: 0, section };
+        assert!(chart.apply(&join(480)).is_err());
+        assert!(chart.apply(&join(0)).is_err());
+        chart.apply(&join(240)).unwrap();
+        assert_eq!(sections(&chart, 0), vec![(240, points.to_vec())]);
+    }
+
+    #[test]
+    fn set_laser_wide() {
+        let mut chart = chart_with_section(&[(0, 0.0), (240, 1.0)]);
+        let wide = |wide: u8| ChartEdit::SetLaserWide {
+            side: 0,
+            section: 240,
+            wide,
+        };
+
+        chart.apply(&wide(2)).unwrap();
+        assert_eq!(chart.note.laser[0][0].wide(), 2);
+        assert_eq!(
+            sections(&chart, 0),
+            vec![(240, vec![(0, 0.25), (240, 0.75)])]
+        );
+        chart.apply(&wide(2)).unwrap();
+        assert!(chart.apply(&wide(3)).is_err());
+
+        chart.apply(&wide(1)).unwrap();
+        assert_eq!(chart.note.laser[0][0].wide(), 1);
+        assert_eq!(sections(&chart, 0), vec![(240, vec![(0, 0.0), (240, 1.0)])]);
+    }
+
+    #[test]
+    fn set_laser_wide_fails_outside_of_the_normal_range() {
+        let mut chart = chart_with_section(&[(0, 0.0), (240, 1.0)]);
+        chart.note.laser[0][0].2 = 2;
+
+        assert!(chart
+            .apply(&ChartEdit::SetLaserWide {
+                side: 0,
+                section: 240,
+                wide: 1,
+            })
+            .is_err());
+        assert_eq!(chart.note.laser[0][0].wide(), 2);
+        assert_eq!(sections(&chart, 0), vec![(240, vec![(0, 0.0), (240, 1.0)])]);
+    }
+
+    #[test]
+    fn bpm_changes() {
+        let mut chart = chart();
+        assert!(chart
+            .apply(&ChartEdit::AddBpm { tick: 0, bpm: 60.0 })
+            .is_err());
+        chart
+            .apply(&ChartEdit::AddBpm {
+                tick: 960,
+                bpm: 180.0,
+            })
+            .unwrap();
+        chart
+            .apply(&ChartEdit::EditBpm {
+                tick: 960,
+                bpm: 200.0,
+            })
+            .unwrap();
+        assert!(chart
+            .apply(&ChartEdit::EditBpm {
+                tick: 480,
+                bpm: 200.0,
+            })
+            .is_err());
+        assert_eq!(chart.beat.bpm, vec![(0, 120.0), (960, 200.0)]);
+
+        chart.apply(&ChartEdit::RemoveBpm { tick: 960 }).unwrap();
+        assert!(chart.apply(&ChartEdit::RemoveBpm { tick: 960 }).is_err());
+        assert_eq!(chart.beat.bpm, vec![(0, 120.0)]);
+    }
+
+    #[test]
+    fn time_signature_changes() {
+        let mut chart = chart();
+
+        assert!(chart
+            .apply(&ChartEdit::AddTimeSig {
+                measure: 0,
+                time_sig: TimeSignature(3, 4),
+            })
+            .is_err());
+        chart
+            .apply(&ChartEdit::AddTimeSig {
+                measure: 2,
+                time_sig: TimeSignature(3, 4),
+            })
+            .unwrap();
+        chart
+            .apply(&ChartEdit::EditTimeSig {
+                measure: 2,
+                time_sig: TimeSignature(7, 8),
+            })
+            .unwrap();
+        assert!(chart
+            .apply(&ChartEdit::EditTimeSig {
+                measure: 1,
+                time_sig: TimeSignature(7, 8),
+            })
+            .is_err());
+        assert_eq!(time_sigs(&chart), vec![(0, 4, 4), (2, 7, 8)]);
+
+        chart
+            .apply(&ChartEdit::RemoveTimeSig { measure: 2 })
+            .unwrap();
+        assert!(chart
+            .apply(&ChartEdit::RemoveTimeSig { measure: 2 })
+            .is_err());
+        assert_eq!(time_sigs(&chart), vec![(0, 4, 4)]);
+    }
+
+    #[test]
+    fn set_meta_and_bgm() {
+        let mut chart = chart();
+        let mut meta = chart.meta.clone();
+        meta.title = "Title".to_string();
+        chart.apply(&ChartEdit::SetMeta(meta)).unwrap();
+        assert_eq!(chart.meta.title, "Title");
+
+        let bgm = BgmInfo {
+            filename: Some("song.ogg".to_string()),
+            offset: 100,
+            vol: 1.0,
+            preview: kson::PreviewInfo {
+                offset: 0,
+                duration: 15000,
+                preview_filename: None,
+            },
+            legacy: kson::LegacyBgmInfo {
+                fp_filenames: vec![],
+            },
+        };
+        chart.apply(&ChartEdit::SetBgm(bgm)).unwrap();
+        let bgm = chart.audio.bgm.as_ref().unwrap();
+        assert_eq!(bgm.filename.as_deref(), Some("song.ogg"));
+        assert_eq!(bgm.offset, 100);
+    }
+
+    #[test]
+    fn camera_points() {
+        let mut chart = chart();
+        let graph = CameraPaths::Zoom;
+        let values = |chart: &mut Chart| -> Vec<(u32, f64, Option<f64>)> {
+            camera_graph_mut(chart, graph)
+                .iter()
+                .map(|p| (p.y, p.v, p.a))
+                .collect()
+        };
+
+        chart
+            .apply(&ChartEdit::AddCameraPoint {
+                graph,
+                point: camera_point(240, 1.0),
+            })
+            .unwrap();
+        assert!(chart
+            .apply(&ChartEdit::AddCameraPoint {
+                graph,
+                point: camera_point(240, 2.0),
+            })
+            .is_err());
+
+        chart
+            .apply(&ChartEdit::SetCameraValue {
+                graph,
+                y: 240,
+                v: 2.0,
+            })
+            .unwrap();
+        chart
+            .apply(&ChartEdit::SetCameraCurve {
+                graph,
+                y: 240,
+                a: 0.25,
+                b: 0.75,
+            })
+            .unwrap();
+        assert_eq!(values(&mut chart), vec![(240, 2.0, Some(0.25))]);
+        assert!(camera_graph_mut(&mut chart, CameraPaths::RotationX).is_empty());
+
+        let missing = [
+            ChartEdit::SetCameraValue {
+                graph,
+                y: 0,
+                v: 2.0,
+            },
+            ChartEdit::SetCameraCurve {
+                graph,
+                y: 0,
+                a: 0.25,
+                b: 0.75,
+            },
+            ChartEdit::RemoveCameraPoint { graph, y: 0 },
+        ];
+        for edit in &missing {
+            assert!(chart.apply(edit).is_err());
+        }
+
+        chart
+            .apply(&ChartEdit::RemoveCameraPoint { graph, y: 240 })
+            .unwrap();
+        assert!(values(&mut chart).is_empty());
+    }
+
+    #[test]
+    fn transforms() {
+        let mut chart = chart();
+        add_note(&mut chart, 0, 0, 0).unwrap();
+
+        assert!(chart
+            .apply(&ChartEdit::Transform {
+                range: None,
+                transform: Transform::Shift(-1),
+            })
+            .is_err());
+        chart
+            .apply(&ChartEdit::Transform {
+                range: None,
+                transform: Transform::Mirror,
+            })
+            .unwrap();
+        assert_eq!(notes(&chart, false, 3), vec![(0, 0)]);
+
+        chart
+            .apply(&ChartEdit::InsertMeasures {
+                measure: 0,
+                count: 1,
+            })
+            .unwrap();
+        assert_eq!(notes(&chart, false, 3), vec![(960, 0)]);
+        assert!(chart
+            .apply(&ChartEdit::InsertMeasures {
+                measure: 0,
+                count: 0,
+            })
+            .is_err());
+
+        chart
+            .apply(&ChartEdit::DeleteMeasures {
+                measure: 0,
+                count: 1,
+            })
+            .unwrap();
+        assert_eq!(notes(&chart, false, 3), vec![(0, 0)]);
+        assert!(chart
+            .apply(&ChartEdit::DeleteMeasures {
+                measure: 0,
+                count: 0,
+            })
+            .is_err());
+
+        chart
+            .apply(&ChartEdit::SetResolution { resolution: 480 })
+            .unwrap();
+        assert_eq!(chart.beat.resolution, 480);
+        assert!(chart
+            .apply(&ChartEdit::SetResolution { resolution: 0 })
+            .is_err());
+
+        chart
+            .apply(&ChartEdit::SetBpmKeepingTime {
+                tick: 0,
+                bpm: 240.0,
+            })
+            .unwrap();
+        assert_eq!(chart.beat.bpm, vec![(0, 240.0)]);
+        assert!(chart
+            .apply(&ChartEdit::SetBpmKeepingTime { tick: 0, bpm: 0.0 })
+            .is_err());
+    }
+
+    #[test]
+    fn batch_fails_if_any_edit_fails() {
+        let mut chart = chart();
+        let add = |y: u32| ChartEdit::AddInterval {
+            fx: false,
+            lane: 0,
+            interval: Interval { y, l: 0 },
+        };
+
+        chart
+            .apply(&ChartEdit::Batch(vec![add(0), add(240)]))
+            .unwrap();
+        assert_eq!(notes(&chart, false, 0), vec![(0, 0), (240, 0)]);
+        assert!(chart
+            .apply(&ChartEdit::Batch(vec![add(480), add(0)]))
+            .is_err());
+    }
+
+    #[test]
+    fn replace_chart() {
+        let mut replacement = chart();
+        add_note(&mut replacement, 1, 0, 0).unwrap();
+        let mut chart = chart();
+
+        chart
+            .apply(&ChartEdit::ReplaceChart(Box::new(replacement)))
+            .unwrap();
+        assert_eq!(notes(&chart, false, 1), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn merge_overwriting_edits() {
+        let merged = Chart::merge_commands(
+            ChartEdit::AddBpm {
+                tick: 960,
+                bpm: 100.0,
+            },
+            ChartEdit::EditBpm {
+                tick: 960,
+                bpm: 150.0,
+            },
+        );
+        assert!(matches!(merged, ChartEdit::AddBpm { tick: 960, bpm } if bpm == 150.0));
+
+        let merged = Chart::merge_commands(
+            ChartEdit::SetCameraValue {
+                graph: CameraPaths::Zoom,
+                y: 0,
+                v: 1.0,
+            },
+            ChartEdit::SetCameraValue {
+                graph: CameraPaths::Zoom,
+                y: 0,
+                v: 2.0,
+            },
+        );
+        assert!(matches!(merged, ChartEdit::SetCameraValue { y: 0, v, .. } if v == 2.0));
+    }
+
+    #[test]
+    fn merge_repeated_resizes() {
+        let mut chart = chart();
+        add_note(&mut chart, 0, 0, 0).unwrap();
+
+        let merged = Chart::merge_commands(resize(0, 0, 120), resize(0, 0, 240));
+        assert!(matches!(
+            merged,
+            ChartEdit::ResizeInterval { y: 0, interval, .. } if interval.y == 0 && interval.l == 240
+        ));
+        let merged = Chart::merge_commands(merged, resize(0, 120, 120));
+        assert!(matches!(
+            merged,
+            ChartEdit::ResizeInterval { y: 0, interval, .. } if interval.y == 120
+        ));
+
+        chart.apply(&merged).unwrap();
+        assert_eq!(notes(&chart, false, 0), vec![(120, 120)]);
+    }
+
+    #[test]
+    fn merge_unrelated_edits_into_a_batch() {
+        let merged = Chart::merge_commands(
+            ChartEdit::RemoveBpm { tick: 960 },
+            ChartEdit::RemoveBpm { tick: 1920 },
+        );
+        let merged = Chart::merge_commands(merged, ChartEdit::RemoveBpm { tick: 2880 });
+
+        assert!(matches!(merged, ChartEdit::Batch(edits) if edits.len() == 3));
+    }
+}
@@ -1,10 +1,20 @@
+use crate::backup;
+use crate::chart_edit::ChartEdit;
+use crate::clipboard::{self, ChartFragment};
+use crate::notifications::Notifications;
+use crate::overlap::OverlapPolicy;
+use crate::recovery;
+use crate::selection::{self, LayerLocks, ObjectId, Selection, SelectionArea};
+use crate::snap::SnapDivision;
 use crate::tools::*;
+use crate::transform::{self, Offset, Transform};
 use crate::*;
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Context as _, Result};
 
 use eframe::egui::epaint::{Mesh, Vertex, WHITE_UV};
 use eframe::egui::{
-    pos2, Align2, Color32, Context, PointerButton, Pos2, Rect, Response, Sense, Shape, Stroke,
+    pos2, Align2, Button, Color32, Context, PointerButton, Pos2, Rect, Response, Sense, Shape,
+    Stroke,
 };
 use eframe::egui::{Painter, Rgba};
 
@@ -24,6 +34,7 @@ use std::io::prelude::*;
 use std::io::BufReader;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::Instant;
 pub const EGUI_ID: &str = "chart_editor";
 
 pub struct MainState {
@@ -37,11 +48,23 @@ pub struct MainState {
     pub cursor_object: Option<Box<dyn CursorObject>>,
     pub current_tool: ChartTool,
     pub actions: action_stack::ActionStack<kson::Chart>,
+    pub chart_revision: u64,
     pub screen: ScreenState,
     pub audio_playback: playback::AudioPlayback,
     pub laser_colors: [Color32; 2],
     pub output_stream: OutputStream,
     pub output_stream_handle: OutputStreamHandle,
+    pub notifications: Notifications,
+    pub selection: Selection,
+    pub snap: SnapDivision,
+    pub overlap_policy: OverlapPolicy,
+    pub layer_locks: LayerLocks,
+    /// Number of backups kept for each chart when saving over it.
+    pub backup_count: usize,
+    /// A recovery file newer than the open chart that the user has not restored or discarded.
+    pub pending_recovery: Option<PathBuf>,
+    last_autosave: Instant,
+    autosave_revision: u64,
 }
 
 #[derive(Copy, Clone)]
@@ -542,10 +565,17 @@ impl ScreenState {
 
 impl MainState {
     pub fn new() -> Result<MainState> {
-        let (new_chart, save_path) = if let Some(Ok(Some((chart, path)))) = std::env::args()
-            .nth(1)
-            .map(|p| open_chart_file(PathBuf::from(p)))
-        {
+        let mut notifications = Notifications::default();
+        let opened = std::env::args().nth(1).and_then(|p| {
+            open_chart_file(PathBuf::from(p))
+                .with_context(|| i18n::fl!("failed_to_open"))
+                .unwrap_or_else(|e| {
+                    notifications.error(&e);
+                    None
+                })
+        });
+
+        let (new_chart, save_path) = if let Some((chart, path)) = opened {
             (chart, Some(path))
         } else {
             let mut c = kson::Chart::new();
@@ -585,6 +615,7 @@ impl MainState {
             audio_playback: playback::AudioPlayback::new(),
             cursor_line: 0,
             actions: action_stack::ActionStack::new(new_chart),
+            chart_revision: 0,
             laser_colors: [
                 Color32::from_rgba_unmultiplied(0, 115, 144, 127),
                 Color32::from_rgba_unmultiplied(194, 6, 140, 127),
@@ -592,13 +623,21 @@ impl MainState {
             sink,
             output_stream,
             output_stream_handle: handle,
+            notifications,
+            selection: Selection::default(),
+            snap: SnapDivision::default(),
+            overlap_policy: OverlapPolicy::default(),
+            layer_locks: LayerLocks::default(),
+            backup_count: backup::DEFAULT_BACKUP_COUNT,
+            pending_recovery: recovery::find(save_path.as_deref()),
+            last_autosave: Instant::now(),
+            autosave_revision: 0,
         };
         Ok(s)
     }
 
     pub fn get_cursor_ms_from_mouse(&self) -> f64 {
-        let tick = self.screen.pos_to_tick(self.mouse_x, self.mouse_y);
-        let tick = tick - (tick % (self.chart.beat.resolution / 2));
+        let tick = self.snap_tick(self.screen.pos_to_tick(self.mouse_x, self.mouse_y));
         self.chart.tick_to_ms(tick)
     }
 
@@ -720,239 +759,722 @@ impl MainState {
         }
     }
 
+    /// Saves the chart, asking for a path if it has none. Returns `false` if the user
+    /// cancelled the dialog.
     pub fn save(&mut self) -> Result<bool> {
-        match (&self.save_path, self.actions.get_current()) {
-            (None, Ok(chart)) => {
-                if let Some(new_path) = save_chart_as(&chart).unwrap_or_else(|e| {
-                    println!("Failed to save chart:");
-                    println!("\t{}", e);
-                    None
-                }) {
-                    self.save_path = Some(new_path);
-                    self.actions.save();
-                    Ok(true)
-                } else {
-                    Ok(false)
-                }
+        let path = match &self.save_path {
+            Some(path) => path.clone(),
+            None => match pick_save_path()? {
+                Some(path) => path,
+                None => return Ok(false),
+            },
+        };
+
+        self.write_chart(&path)?;
+        self.saved_to(path);
+        Ok(true)
+    }
+
+    fn write_chart(&mut self, path: &Path) -> Result<()> {
+        profile_scope!("Write kson");
+        let chart = self.actions.current()?;
+        backup::save_chart(path, chart, self.backup_count)
+    }
+
+    /// Marks the current state as saved to `path` and removes recovery files made obsolete by it.
+    fn saved_to(&mut self, path: PathBuf) {
+        let old_path = self.save_path.replace(path);
+        self.actions.save();
+        // The recovery file stays until the user has restored or discarded it
+        if self.pending_recovery.is_some() {
+            return;
+        }
+        for path in [old_path.as_deref(), self.save_path.as_deref()] {
+            if let Err(e) = recovery::remove(path) {
+                log::warn!("Could not remove recovery file: {:#}", e);
             }
-            (Some(path), Ok(chart)) => {
-                let mut file = File::create(&path).unwrap();
-                profile_scope!("Write kson");
-                file.write_all(serde_json::to_string(&chart)?.as_bytes())?;
-                self.actions.save();
+        }
+    }
+
+    /// Writes the current chart to the recovery file if it changed since the last autosave.
+    fn autosave(&mut self) -> Result<()> {
+        // Don't touch the recovery file while the user hasn't answered the recovery dialog
+        if self.pending_recovery.is_some() {
+            return Ok(());
+        }
+        if self.actions.saved() {
+            return recovery::remove(self.save_path.as_deref());
+        }
+        if self.autosave_revision == self.actions.revision() {
+            return Ok(());
+        }
+
+        profile_scope!("Autosave");
+        let path = recovery::recovery_path(self.save_path.as_deref())?;
+        recovery::write(&path, self.actions.current()?)?;
+        self.autosave_revision = self.actions.revision();
+        Ok(())
+    }
+
+    pub fn restore_recovery(&mut self) -> Result<()> {
+        if let Some(path) = self.pending_recovery.take() {
+            let chart = utils::read_kson(&path)?;
+            self.actions.new_action(
+                i18n::fl!("restore_unsaved_changes"),
+                ChartEdit::ReplaceChart(Box::new(chart)),
+            );
+        }
+        Ok(())
+    }
+
+    /// Copies the selected objects to the clipboard, returns false if nothing is selected.
+    fn copy_selection(&mut self, ctx: &Context) -> Result<bool> {
+        match ChartFragment::from_selection(&self.chart, &self.selection) {
+            Some(fragment) => {
+                ctx.output().copied_text = fragment.to_json()?;
                 Ok(true)
             }
-            _ => bail!("Could not save chart."),
+            None => Ok(false),
         }
     }
 
-    pub fn update(&mut self, ctx: &Context) -> Result<()> {
-        while let Some(e) = self.gui_event_queue.pop_front() {
-            match e {
-                GuiEvent::Open => {
-                    if let Some(new_chart) = open_chart().unwrap_or_else(|e| {
-                        println!("Failed to open chart:");
-                        println!("\t{}", e);
-                        None
-                    }) {
-                        self.chart = new_chart.0.clone();
-                        self.actions.reset(new_chart.0);
-                        self.save_path = Some(new_chart.1);
+    /// Moves the selected objects as one action, consecutive moves with the same
+    /// `merge_key` are undone together.
+    pub fn move_selection(&mut self, offset: Offset, merge_key: Option<&str>) {
+        if let Some((edit, moved)) = transform::move_objects(&self.chart, &self.selection, offset) {
+            let description = i18n::fl!("move_objects");
+            match merge_key {
+                Some(key) => self.actions.new_merging_action(key, description, edit),
+                None => self.actions.new_action(description, edit),
+            }
+            self.selection.replace(&moved);
+        }
+    }
+
+    /// Applies `transform` as one action to the tick range covered by the selection, or to
+    /// the whole chart.
+    fn transform(&mut self, transform: Transform, selection_only: bool) {
+        let range = if selection_only {
+            match self.selection.tick_range(&self.chart) {
+                Some(range) => Some(range),
+                None => return,
+            }
+        } else {
+            None
+        };
+        let description = match transform {
+            Transform::Mirror => i18n::fl!("mirror"),
+            Transform::Shift(ticks) => i18n::fl!("shift_by", ticks = ticks),
+            Transform::Scale {
+                numerator,
+                denominator,
+            } => i18n::fl!("scale_by", ratio = format!("{}/{}", numerator, denominator)),
+        };
+
+        self.actions
+            .new_action(description, ChartEdit::Transform { range, transform });
+        self.selection.clear();
+    }
+
+    /// Rescales the chart to `resolution` as one action, the cursor stays on the same beat.
+    pub fn change_resolution(&mut self, resolution: u32) {
+        let old_resolution = self.chart.beat.resolution.max(1) as u64;
+        self.actions.new_action(
+            i18n::fl!("change_resolution_to", resolution = resolution),
+            ChartEdit::SetResolution { resolution },
+        );
+        self.cursor_line = (self.cursor_line as u64 * resolution as u64 / old_resolution) as u32;
+        self.selection.clear();
+    }
+
+    /// Checkboxes for the layer locks, objects on a layer that gets locked are deselected.
+    pub fn layer_locks_menu(&mut self, ui: &mut Ui) {
+        let locks = &mut self.layer_locks;
+        let changed = [
+            ui.checkbox(&mut locks.bt, "BT"),
+            ui.checkbox(&mut locks.fx, "FX"),
+            ui.checkbox(&mut locks.laser, i18n::fl!("lasers")),
+            ui.checkbox(&mut locks.beat, i18n::fl!("bpm_and_time_signatures")),
+        ]
+        .iter()
+        .any(|r| r.changed());
+        if changed {
+            self.selection.retain_unlocked(&self.layer_locks);
+        }
+    }
+
+    /// Laser sections that are selected or have selected points.
+    pub fn selected_laser_sections(&self) -> Vec<(usize, u32)> {
+        let mut sections: Vec<(usize, u32)> = self
+            .selection
+            .iter()
+            .filter_map(|o| match *o {
+                ObjectId::LaserSection { side, tick } => Some((side, tick)),
+                ObjectId::LaserPoint { side, section, .. } => Some((side, section)),
+                _ => None,
+            })
+            .collect();
+        sections.sort_unstable();
+        sections.dedup();
+        sections
+    }
+
+    /// Switches the selected laser sections between the normal and wide range as one
+    /// action, wide sections become normal only if they all fit.
+    fn toggle_laser_wide(&mut self) {
+        let sections = self.selected_laser_sections();
+        let lasers = &self.chart.note.laser;
+        let to_wide = sections.iter().any(|(side, tick)| {
+            lasers[*side]
+                .iter()
+                .find(|s| s.tick() == *tick)
+                .map_or(false, |s| s.wide() != 2)
+        });
+        let wide = if to_wide { 2 } else { 1 };
+
+        let edits: Vec<ChartEdit> = sections
+            .into_iter()
+            .map(|(side, section)| ChartEdit::SetLaserWide {
+                side,
+                section,
+                wide,
+            })
+            .collect();
+        if !edits.is_empty() {
+            self.actions
+                .new_action(i18n::fl!("toggle_laser_wide"), ChartEdit::Batch(edits));
+        }
+    }
+
+    /// The selected notes and their lanes.
+    fn selected_intervals(&self) -> Vec<(bool, usize, Interval)> {
+        self.selection
+            .iter()
+            .filter_map(|o| match *o {
+                ObjectId::Interval { fx, lane, y } => {
+                    let notes = if fx {
+                        self.chart.note.fx.get(lane)?
+                    } else {
+                        self.chart.note.bt.get(lane)?
+                    };
+                    let index = notes.binary_search_by_key(&y, |n| n.y).ok()?;
+                    Some((fx, lane, notes[index]))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Changes the length of the selected notes as one action, chips become holds one snap
+    /// step long.
+    fn convert_intervals(&mut self, to_long: bool) {
+        let resolution = self.chart.beat.resolution;
+        let snap = self.snap;
+        let edits: Vec<ChartEdit> = self
+            .selected_intervals()
+            .into_iter()
+            .filter(|(_, _, n)| (n.l == 0) == to_long)
+            .map(|(fx, lane, n)| ChartEdit::ResizeInterval {
+                fx,
+                lane,
+                y: n.y,
+                interval: Interval {
+                    y: n.y,
+                    l: if to_long {
+                        (snap.next(n.y, resolution) - n.y).max(1)
+                    } else {
+                        0
+                    },
+                },
+            })
+            .collect();
+
+        if !edits.is_empty() {
+            let description = if to_long {
+                i18n::fl!("convert_to_long")
+            } else {
+                i18n::fl!("convert_to_chip")
+            };
+            self.actions
+                .new_action(description, ChartEdit::Batch(edits));
+        }
+    }
+
+    /// Context menu entries for the selection, tools add their own below these.
+    pub fn context_menu(&mut self, ui: &mut Ui) {
+        if ui
+            .add_enabled(!self.selection.is_empty(), Button::new(i18n::fl!("delete")))
+            .clicked()
+        {
+            if let Some(edit) = clipboard::delete(&self.selection) {
+                self.actions.new_action(i18n::fl!("delete_objects"), edit);
+            }
+            self.selection.clear();
+            ui.close_menu();
+        }
+
+        let intervals = self.selected_intervals();
+        if intervals.iter().any(|(_, _, n)| n.l == 0)
+            && ui.button(i18n::fl!("convert_to_long")).clicked()
+        {
+            self.convert_intervals(true);
+            ui.close_menu();
+        }
+        if intervals.iter().any(|(_, _, n)| n.l > 0)
+            && ui.button(i18n::fl!("convert_to_chip")).clicked()
+        {
+            self.convert_intervals(false);
+            ui.close_menu();
+        }
+        if let [(fx, lane, _)] = intervals[..] {
+            ui.menu_button(i18n::fl!("change_lane"), |ui| {
+                let lanes = if fx { 2 } else { 4 };
+                for target in 0..lanes {
+                    if ui
+                        .add_enabled(target != lane, Button::new(lane_name(fx, target)))
+                        .clicked()
+                    {
+                        let offset = target as i32 - lane as i32;
+                        self.move_selection(
+                            Offset {
+                                ticks: 0,
+                                bt_lanes: offset,
+                                fx_lanes: offset,
+                            },
+                            None,
+                        );
+                        ui.close_menu();
                     }
                 }
-                GuiEvent::Save => {
-                    self.save()?;
+            });
+        }
+
+        let points: Vec<(usize, u32, u32)> = self
+            .selection
+            .iter()
+            .filter_map(|o| match *o {
+                ObjectId::LaserPoint { side, section, ry } => Some((side, section, ry)),
+                _ => None,
+            })
+            .collect();
+        if let [(side, section, ry)] = points[..] {
+            self.laser_point_menu(ui, side, section, ry);
+        }
+
+        if !self.selected_laser_sections().is_empty()
+            && ui.button(i18n::fl!("toggle_laser_wide")).clicked()
+        {
+            self.toggle_laser_wide();
+            ui.close_menu();
+        }
+    }
+
+    /// Splitting, joining and removing a single selected laser point.
+    fn laser_point_menu(&mut self, ui: &mut Ui, side: usize, section: u32, ry: u32) {
+        let sections = match self.chart.note.laser.get(side) {
+            Some(sections) => sections,
+            None => return,
+        };
+        let points = match sections.iter().find(|s| s.tick() == section) {
+            Some(laser) => &laser.1,
+            None => return,
+        };
+        let index = match points.binary_search_by_key(&ry, |p| p.ry) {
+            Ok(index) => index,
+            Err(_) => return,
+        };
+        let side_name = laser_side_name(side);
+
+        let mut edit = None;
+        if index + 1 == points.len() {
+            let has_next = sections.iter().any(|s| s.tick() > section);
+            if ui
+                .add_enabled(
+                    has_next,
+                    Button::new(i18n::fl!("join_laser", side = side_name.clone())),
+                )
+                .clicked()
+            {
+                edit = Some((
+                    i18n::fl!("join_laser", side = side_name.clone()),
+                    ChartEdit::JoinLaserSections { side, section },
+                ));
+            }
+        } else if ui
+            .add_enabled(
+                index >= 1 && index + 3 <= points.len(),
+                Button::new(i18n::fl!("split_laser", side = side_name.clone())),
+            )
+            .clicked()
+        {
+            edit = Some((
+                i18n::fl!("split_laser", side = side_name.clone()),
+                ChartEdit::SplitLaserSection { side, section, ry },
+            ));
+        }
+
+        if ui
+            .add_enabled(
+                points.len() > 2,
+                Button::new(i18n::fl!("remove_laser_point", side = side_name.clone())),
+            )
+            .clicked()
+        {
+            edit = Some((
+                i18n::fl!("remove_laser_point", side = side_name),
+                ChartEdit::RemoveLaserPoint { side, section, ry },
+            ));
+        }
+
+        if let Some((description, edit)) = edit {
+            self.actions.new_action(description, edit);
+            self.selection.clear();
+            ui.close_menu();
+        }
+    }
+
+    /// Moves the selection by snap steps in time and by lanes.
+    fn nudge_selection(&mut self, steps: i32, lanes: i32) {
+        let resolution = self.chart.beat.resolution;
+        let ticks = match transform::start_tick(&self.chart, &self.selection) {
+            Some(start) if steps > 0 => self.snap.next(start, resolution) as i64 - start as i64,
+            Some(start) if steps < 0 => self.snap.previous(start, resolution) as i64 - start as i64,
+            _ => 0,
+        };
+
+        self.move_selection(
+            Offset {
+                ticks,
+                bt_lanes: lanes,
+                fx_lanes: lanes,
+            },
+            Some("nudge"),
+        );
+    }
+
+    pub fn snap_tick(&self, tick: u32) -> u32 {
+        self.snap.snap(tick, self.chart.beat.resolution)
+    }
+
+    pub fn discard_recovery(&mut self) -> Result<()> {
+        self.pending_recovery = None;
+        recovery::remove(self.save_path.as_deref())
+    }
+
+    pub fn update(&mut self, ctx: &Context) -> Result<()> {
+        while let Some(e) = self.gui_event_queue.pop_front() {
+            if let Err(e) = self.handle_gui_event(e, ctx) {
+                self.notifications.error(&e);
+            }
+        }
+        if self.chart_revision != self.actions.revision() {
+            match self.actions.current() {
+                Ok(current_chart) => {
+                    self.chart = current_chart.clone();
+                    self.chart_revision = self.actions.revision();
+                    self.selection.retain_existing(&self.chart);
+                    self.selection.retain_unlocked(&self.layer_locks);
                 }
-                GuiEvent::SaveAs => {
-                    if let Ok(chart) = self.actions.get_current() {
-                        if let Some(new_path) = save_chart_as(&chart).unwrap_or_else(|e| {
-                            println!("Failed to save chart:");
-                            println!("\t{}", e);
-                            None
-                        }) {
-                            self.save_path = Some(new_path);
-                            self.actions.save();
-                        }
-                    }
+                // The failing action has been rolled back, the next update picks up the result
+                Err(e) => self.notifications.error(&e),
+            }
+        }
+
+        if self.last_autosave.elapsed() >= recovery::AUTOSAVE_INTERVAL {
+            self.last_autosave = Instant::now();
+            if let Err(e) = self.autosave() {
+                self.notifications
+                    .error(&e.context(i18n::fl!("failed_to_autosave")));
+            }
+        }
+
+        let delta_time = (10.0 * ctx.input().unstable_dt).min(1.0);
+        if self.screen.update(delta_time, self.chart.beat.resolution)
+            || self.audio_playback.is_playing()
+        {
+            ctx.request_repaint();
+        }
+        let tick = self.audio_playback.get_tick(&self.chart);
+        self.audio_playback.update(tick);
+        Ok(())
+    }
+
+    fn handle_gui_event(&mut self, event: GuiEvent, ctx: &Context) -> Result<()> {
+        match event {
+            GuiEvent::Open => {
+                if let Some(new_chart) =
+                    open_chart().with_context(|| i18n::fl!("failed_to_open"))?
+                {
+                    self.chart = new_chart.0.clone();
+                    self.actions.reset(new_chart.0);
+                    self.save_path = Some(new_chart.1);
+                    self.selection.clear();
+                    self.pending_recovery = recovery::find(self.save_path.as_deref());
                 }
-                GuiEvent::ToolChanged(new_tool) => {
-                    if self.current_tool != new_tool {
-                        self.cursor_object = match new_tool {
-                            ChartTool::None => None,
-                            ChartTool::BT => Some(Box::new(ButtonInterval::new(false))),
-                            ChartTool::FX => Some(Box::new(ButtonInterval::new(true))),
-                            ChartTool::LLaser => Some(Box::new(LaserTool::new(false))),
-                            ChartTool::RLaser => Some(Box::new(LaserTool::new(true))),
-                            ChartTool::BPM => Some(Box::new(BpmTool::new())),
-                            ChartTool::TimeSig => Some(Box::new(TimeSigTool::new())),
-                            ChartTool::Camera => Some(Box::new(CameraTool::default())),
-                        };
-                        self.current_tool = new_tool;
-                        ctx.request_repaint();
-                    }
+            }
+            GuiEvent::Save => {
+                self.save().with_context(|| i18n::fl!("failed_to_save"))?;
+            }
+            GuiEvent::SaveAs => {
+                if let Some(new_path) = pick_save_path()? {
+                    self.write_chart(&new_path)
+                        .with_context(|| i18n::fl!("failed_to_save"))?;
+                    self.saved_to(new_path);
                 }
-                GuiEvent::Undo => self.actions.undo(),
-                GuiEvent::Redo => self.actions.redo(),
-                GuiEvent::NewChart(new_chart_opts) => {
-                    let mut new_chart = kson::Chart::new();
-                    new_chart.beat.bpm.push((0, 120.0));
-                    new_chart.beat.time_sig.push((0, kson::TimeSignature(4, 4)));
-
-                    let audio_pathbuf = std::path::PathBuf::from(new_chart_opts.audio);
-                    new_chart.audio.bgm = Some(kson::BgmInfo {
-                        filename: Some(String::from(
-                            audio_pathbuf.file_name().unwrap().to_str().unwrap(),
-                        )),
-                        offset: 0,
-                        vol: 1.0,
-                        preview: {
-                            kson::PreviewInfo {
-                                offset: 0,
-                                duration: 15000,
-                                preview_filename: None,
-                            }
-                        },
-                        legacy: kson::LegacyBgmInfo {
-                            fp_filenames: vec![],
-                        },
-                    });
-                    self.save_path = if let Some(save_path) = new_chart_opts.destination {
-                        //copy audio file
-                        let mut audio_new_path = save_path.clone();
-                        audio_new_path.push(audio_pathbuf.file_name().unwrap());
-                        if !audio_new_path.exists() {
-                            std::fs::copy(audio_pathbuf, audio_new_path).unwrap();
-                        }
-                        Some(save_path)
-                    } else {
-                        Some(audio_pathbuf.parent().unwrap().to_path_buf())
+            }
+            GuiEvent::RestoreBackup(path) => {
+                let chart = utils::read_kson(&path)
+                    .with_context(|| i18n::fl!("failed_to_restore_backup"))?;
+                self.actions.new_action(
+                    i18n::fl!("restore_backup"),
+                    ChartEdit::ReplaceChart(Box::new(chart)),
+                );
+            }
+            GuiEvent::ToolChanged(new_tool) => {
+                if self.current_tool != new_tool {
+                    self.cursor_object = match new_tool {
+                        ChartTool::None => None,
+                        ChartTool::BT => Some(Box::new(ButtonInterval::new(false))),
+                        ChartTool::FX => Some(Box::new(ButtonInterval::new(true))),
+                        ChartTool::LLaser => Some(Box::new(LaserTool::new(false))),
+                        ChartTool::RLaser => Some(Box::new(LaserTool::new(true))),
+                        ChartTool::BPM => Some(Box::new(BpmTool::new())),
+                        ChartTool::TimeSig => Some(Box::new(TimeSigTool::new())),
+                        ChartTool::Camera => Some(Box::new(CameraTool::default())),
+                        ChartTool::Select => Some(Box::new(SelectTool::new())),
+                        ChartTool::Eraser => Some(Box::new(EraserTool::default())),
                     };
-
-                    let mut kson_path = self.save_path.clone().unwrap();
-                    kson_path.push(new_chart_opts.filename);
-                    kson_path.set_extension("kson");
-                    self.save_path = Some(kson_path.clone());
-                    if let Ok(mut file) = File::create(kson_path) {
-                        file.write_all(serde_json::to_string(&new_chart).unwrap().as_bytes())
-                            .unwrap();
+                    self.current_tool = new_tool;
+                    self.actions.seal();
+                    ctx.request_repaint();
+                }
+            }
+            GuiEvent::SelectAll => {
+                let locks = self.layer_locks;
+                self.selection.clear();
+                self.selection.extend(
+                    SelectionArea::everything()
+                        .objects(&self.chart)
+                        .into_iter()
+                        .filter(|o| !locks.is_locked(o)),
+                );
+                self.actions.seal();
+            }
+            GuiEvent::ClearSelection => {
+                self.selection.clear();
+                self.actions.seal();
+            }
+            GuiEvent::ToggleLaserWide => self.toggle_laser_wide(),
+            GuiEvent::NudgeUp => self.nudge_selection(1, 0),
+            GuiEvent::NudgeDown => self.nudge_selection(-1, 0),
+            GuiEvent::NudgeLeft => self.nudge_selection(0, -1),
+            GuiEvent::NudgeRight => self.nudge_selection(0, 1),
+            GuiEvent::SnapFiner => self.snap = self.snap.finer(),
+            GuiEvent::SnapCoarser => self.snap = self.snap.coarser(),
+            GuiEvent::Copy => {
+                self.copy_selection(ctx)?;
+            }
+            GuiEvent::Cut => {
+                if self.copy_selection(ctx)? {
+                    if let Some(edit) = clipboard::cut(&self.chart, &self.selection) {
+                        self.actions.new_action(i18n::fl!("cut"), edit);
                     }
-                    self.actions.reset(new_chart.clone());
-                    self.chart = new_chart;
                 }
-                GuiEvent::ExportKsh => {
-                    if let Ok(chart) = self.actions.get_current() {
-                        let dialog_result = nfd::open_save_dialog(Some("ksh"), None);
-
-                        if let Ok(nfd::Response::Okay(file_path)) = dialog_result {
-                            let mut path = PathBuf::from(file_path);
-                            path.set_extension("ksh");
-                            let file = File::create(&path).unwrap();
-                            profile_scope!("Write KSH");
-                            chart.to_ksh(file)?;
+            }
+            GuiEvent::Paste(text) => {
+                let fragment = ChartFragment::from_json(&text)
+                    .with_context(|| i18n::fl!("failed_to_paste"))?;
+                if let Some((edit, pasted)) =
+                    fragment.paste(&self.chart, self.snap_tick(self.cursor_line))
+                {
+                    self.actions.new_action(i18n::fl!("paste"), edit);
+                    self.selection.clear();
+                    self.selection.extend(pasted);
+                }
+            }
+            GuiEvent::Transform {
+                transform,
+                selection_only,
+            } => self.transform(transform, selection_only),
+            GuiEvent::InsertMeasures(count) => {
+                let measure = self.chart.tick_to_measure(self.cursor_line);
+                self.actions.new_action(
+                    i18n::fl!("insert_measures", count = count),
+                    ChartEdit::InsertMeasures { measure, count },
+                );
+                self.selection.clear();
+            }
+            GuiEvent::DeleteMeasures(count) => {
+                let measure = self.chart.tick_to_measure(self.cursor_line);
+                self.actions.new_action(
+                    i18n::fl!("delete_measures", count = count),
+                    ChartEdit::DeleteMeasures { measure, count },
+                );
+                self.selection.clear();
+            }
+            GuiEvent::Undo => self.actions.undo(),
+            GuiEvent::Redo => self.actions.redo(),
+            GuiEvent::NewChart(new_chart_opts) => {
+                let mut new_chart = kson::Chart::new();
+                new_chart.beat.bpm.push((0, 120.0));
+                new_chart.beat.time_sig.push((0, kson::TimeSignature(4, 4)));
+
+                let audio_pathbuf = std::path::PathBuf::from(new_chart_opts.audio);
+                let audio_filename = audio_pathbuf
+                    .file_name()
+                    .and_then(OsStr::to_str)
+                    .ok_or_else(|| anyhow!(i18n::fl!("invalid_audio_file")))?
+                    .to_string();
+                new_chart.audio.bgm = Some(kson::BgmInfo {
+                    filename: Some(audio_filename.clone()),
+                    offset: 0,
+                    vol: 1.0,
+                    preview: {
+                        kson::PreviewInfo {
+                            offset: 0,
+                            duration: 15000,
+                            preview_filename: None,
                         }
+                    },
+                    legacy: kson::LegacyBgmInfo {
+                        fp_filenames: vec![],
+                    },
+                });
+                let mut kson_path = if let Some(save_path) = new_chart_opts.destination {
+                    //copy audio file
+                    let mut audio_new_path = save_path.clone();
+                    audio_new_path.push(&audio_filename);
+                    if !audio_new_path.exists() {
+                        std::fs::copy(&audio_pathbuf, audio_new_path)
+                            .with_context(|| i18n::fl!("failed_to_copy_audio"))?;
                     }
+                    save_path
+                } else {
+                    audio_pathbuf
+                        .parent()
+                        .map(Path::to_path_buf)
+                        .unwrap_or_default()
+                };
+
+                kson_path.push(new_chart_opts.filename);
+                kson_path.set_extension("kson");
+                self.actions.reset(new_chart.clone());
+                self.chart = new_chart;
+                self.selection.clear();
+                self.save_path = Some(kson_path.clone());
+
+                utils::write_atomic(&kson_path, &serde_json::to_vec(&self.chart)?)
+                    .with_context(|| i18n::fl!("failed_to_save"))?;
+            }
+            GuiEvent::ExportKsh => {
+                let chart = self.actions.get_current()?;
+                let dialog_result = nfd::open_save_dialog(Some("ksh"), None)?;
+
+                if let nfd::Response::Okay(file_path) = dialog_result {
+                    let mut path = PathBuf::from(file_path);
+                    path.set_extension("ksh");
+                    let file =
+                        File::create(&path).with_context(|| i18n::fl!("failed_to_export"))?;
+                    profile_scope!("Write KSH");
+                    chart
+                        .to_ksh(file)
+                        .with_context(|| i18n::fl!("failed_to_export"))?;
+                    self.notifications
+                        .info(i18n::fl!("exported", path = path.display().to_string()));
                 }
-                GuiEvent::Play => {
-                    if self.audio_playback.is_playing() {
-                        self.audio_playback.stop()
-                    } else if let Some(path) = &self.save_path {
-                        let path = Path::new(path).parent().unwrap();
-                        if let Some(bgm) = &self.chart.audio.bgm {
-                            if let Some(filename) = &bgm.filename {
-                                let filename = &filename.split(';').next().unwrap();
-                                let path = path.join(Path::new(filename));
-                                info!("Playing file: {}", path.display());
-                                let path = path.to_str().unwrap();
-                                match self.audio_playback.open_path(path) {
-                                    Ok(_) => {
-                                        let ms = self.chart.tick_to_ms(self.cursor_line)
-                                            + bgm.offset as f64;
-                                        let ms = ms.max(0.0);
-                                        self.audio_playback.build_effects(&self.chart);
-                                        self.audio_playback.set_poistion(ms);
-                                        self.audio_playback.play();
-                                        if self.sink.len() > 0 {
-                                            self.sink.clear();
-                                            self.sink.sleep_until_end();
+            }
+            GuiEvent::Play => {
+                if self.audio_playback.is_playing() {
+                    self.audio_playback.stop()
+                } else if let Some(path) = self.save_path.as_ref().and_then(|p| p.parent()) {
+                    if let Some(bgm) = &self.chart.audio.bgm {
+                        if let Some(filename) = &bgm.filename {
+                            let filename = &filename.split(';').next().unwrap();
+                            let path = path.join(Path::new(filename));
+                            info!("Playing file: {}", path.display());
+                            let path = path.to_string_lossy();
+                            match self.audio_playback.open_path(&path) {
+                                Ok(_) => {
+                                    let ms =
+                                        self.chart.tick_to_ms(self.cursor_line) + bgm.offset as f64;
+                                    let ms = ms.max(0.0);
+                                    self.audio_playback.build_effects(&self.chart);
+                                    self.audio_playback.set_poistion(ms);
+                                    self.audio_playback.play();
+                                    let source = match self.audio_playback.get_source() {
+                                        Some(source) => source,
+                                        None => {
+                                            self.audio_playback.stop();
+                                            bail!(
+                                                "{}: {}",
+                                                i18n::fl!("failed_to_play"),
+                                                i18n::fl!("audio_source_unavailable")
+                                            )
                                         }
-                                        self.sink.append(
-                                            self.audio_playback
-                                                .get_source()
-                                                .expect("Source not available"),
-                                        );
+                                    };
+                                    if self.sink.len() > 0 {
+                                        self.sink.clear();
+                                        self.sink.sleep_until_end();
+                                    }
+                                    self.sink.append(source);
 
-                                        self.audio_playback.play();
+                                    self.audio_playback.play();
 
-                                        self.sink.play();
-                                    }
-                                    Err(msg) => {
-                                        println!("{}", msg);
-                                    }
+                                    self.sink.play();
+                                }
+                                Err(msg) => {
+                                    bail!("{}: {}", i18n::fl!("failed_to_play"), msg)
                                 }
                             }
                         }
                     }
                 }
-                GuiEvent::Home => self.screen.x_offset_target = 0.0,
-                GuiEvent::End => {
-                    let mut target: f32 = 0.0;
+            }
+            GuiEvent::Home => self.screen.x_offset_target = 0.0,
+            GuiEvent::End => {
+                let mut target: f32 = 0.0;
 
-                    //check pos of last bt
-                    for i in 0..4 {
-                        if let Some(note) = self.chart.note.bt[i].last() {
-                            target = target.max(
-                                self.screen.tick_to_pos(note.y + note.l).0 + self.screen.x_offset,
-                            )
-                        }
+                //check pos of last bt
+                for i in 0..4 {
+                    if let Some(note) = self.chart.note.bt[i].last() {
+                        target = target
+                            .max(self.screen.tick_to_pos(note.y + note.l).0 + self.screen.x_offset)
                     }
+                }
 
-                    //check pos of last fx
-                    for i in 0..2 {
-                        if let Some(note) = self.chart.note.fx[i].last() {
-                            target = target.max(
-                                self.screen.tick_to_pos(note.y + note.l).0 + self.screen.x_offset,
-                            )
-                        }
+                //check pos of last fx
+                for i in 0..2 {
+                    if let Some(note) = self.chart.note.fx[i].last() {
+                        target = target
+                            .max(self.screen.tick_to_pos(note.y + note.l).0 + self.screen.x_offset)
                     }
+                }
 
-                    //check pos of last lasers
-                    for i in 0..2 {
-                        if let Some(section) = self.chart.note.laser[i].last() {
-                            if let Some(segment) = section.last() {
-                                target = target.max(
-                                    self.screen.tick_to_pos(segment.ry + section.tick()).0
-                                        + self.screen.x_offset,
-                                )
-                            }
+                //check pos of last lasers
+                for i in 0..2 {
+                    if let Some(section) = self.chart.note.laser[i].last() {
+                        if let Some(segment) = section.last() {
+                            target = target.max(
+                                self.screen.tick_to_pos(segment.ry + section.tick()).0
+                                    + self.screen.x_offset,
+                            )
                         }
                     }
-
-                    self.screen.x_offset_target = target - (target % self.screen.track_spacing())
-                }
-                GuiEvent::Next => {
-                    self.screen.x_offset_target = (self.screen.x_offset_target
-                        - (self.screen.w - (self.screen.w % self.screen.track_spacing())))
-                    .max(0.0)
                 }
-                GuiEvent::Previous => {
-                    self.screen.x_offset_target +=
-                        self.screen.w - (self.screen.w % self.screen.track_spacing())
-                }
-                _ => (),
-            }
-        }
-        if let Ok(current_chart) = self.actions.get_current() {
-            self.chart = current_chart;
-        }
 
-        let delta_time = (10.0 * ctx.input().unstable_dt).min(1.0);
-        if self.screen.update(delta_time, self.chart.beat.resolution)
-            || self.audio_playback.is_playing()
-        {
-            ctx.request_repaint();
+                self.screen.x_offset_target = target - (target % self.screen.track_spacing())
+            }
+            GuiEvent::Next => {
+                self.screen.x_offset_target = (self.screen.x_offset_target
+                    - (self.screen.w - (self.screen.w % self.screen.track_spacing())))
+                .max(0.0)
+            }
+            GuiEvent::Previous => {
+                self.screen.x_offset_target +=
+                    self.screen.w - (self.screen.w % self.screen.track_spacing())
+            }
+            _ => (),
         }
-        let tick = self.audio_playback.get_tick(&self.chart);
-        self.audio_playback.update(tick);
         Ok(())
     }
 
@@ -971,6 +1493,7 @@ impl MainState {
         let mut fx_builder = Vec::new();
         let mut long_fx_builder = Vec::new();
         let mut laser_builder = Vec::new();
+        let mut wide_labels = Vec::new();
         let min_tick_render = self.screen.pos_to_tick(-100.0, self.screen.h);
         let max_tick_render = self.screen.pos_to_tick(self.screen.w + 50.0, 0.0);
         info!("Sink: {}, {}", self.sink.is_paused(), self.sink.len());
@@ -1001,6 +1524,23 @@ impl MainState {
                 //measure & beat lines
                 let x = self.screen.track_width / 2.0 + self.screen.lane_width();
                 let w = self.screen.lane_width() * 4.0;
+
+                //snap grid lines, skipped when they would be too close to tell apart
+                let resolution = self.chart.beat.resolution;
+                let grid_spacing = self.snap.spacing(resolution).unwrap_or(0.0);
+                if grid_spacing * self.screen.tick_height >= 4.0 {
+                    for tick in self
+                        .snap
+                        .lines(min_tick_render, max_tick_render, resolution)
+                    {
+                        let (tx, y) = self.screen.tick_to_pos(tick);
+                        track_measure_builder.push(Shape::rect_filled(
+                            rect_xy_wh([tx + x, painter.round_to_pixel(y), w, -1.0]),
+                            0.0,
+                            Rgba::from_gray(0.2),
+                        ));
+                    }
+                }
                 for (tick, is_measure) in self.chart.beat_line_iter() {
                     if tick < min_tick_render {
                         continue;
@@ -1141,6 +1681,15 @@ impl MainState {
                             *color,
                             false,
                         )?;
+
+                        if section.wide() == 2 {
+                            let (x, y) = self.screen.tick_to_pos(y_base);
+                            let v = section.1[0].v;
+                            let x = x
+                                + self.screen.track_width / 2.0
+                                + selection::laser_lane(v, true) * self.screen.lane_width();
+                            wide_labels.push((pos2(x, y + 2.0), *color));
+                        }
                     }
                 }
             }
@@ -1180,14 +1729,28 @@ impl MainState {
             {
                 profile_scope!("Laser Mesh");
                 painter.extend(laser_builder.into_iter().map(Shape::mesh).collect());
+                for (pos, color) in wide_labels {
+                    painter.text(
+                        pos,
+                        Align2::CENTER_TOP,
+                        "2x",
+                        FontId::monospace(10.0),
+                        color,
+                    );
+                }
             }
         }
 
+        {
+            profile_scope!("Selection");
+            self.selection.draw(self, &painter);
+        }
+
         if let Some(cursor) = &self.cursor_object {
             profile_scope!("Tool");
-            cursor
-                .draw(self, &painter)
-                .unwrap_or_else(|e| println!("{}", e));
+            if let Err(e) = cursor.draw(self, &painter) {
+                log::error!("{:#}", e);
+            }
         }
 
         {
@@ -1267,10 +1830,8 @@ impl MainState {
 
     pub fn drag_start(&mut self, button: PointerButton, x: f32, y: f32, modifiers: &Modifiers) {
         if let PointerButton::Primary = button {
-            let res = self.chart.beat.resolution;
             let lane = self.screen.pos_to_lane(x);
-            let tick = self.screen.pos_to_tick(x, y);
-            let tick = tick - (tick % (res / 2));
+            let tick = self.snap_tick(self.screen.pos_to_tick(x, y));
             let tick_f = self.screen.pos_to_tick_f(x, y);
             if let Some(ref mut cursor) = self.cursor_object {
                 cursor.drag_start(
@@ -1290,9 +1851,8 @@ impl MainState {
     pub fn drag_end(&mut self, button: PointerButton, x: f32, y: f32) {
         if let PointerButton::Primary = button {
             let lane = self.screen.pos_to_lane(x);
-            let tick = self.screen.pos_to_tick(x, y);
+            let tick = self.snap_tick(self.screen.pos_to_tick(x, y));
             let tick_f = self.screen.pos_to_tick_f(x, y);
-            let tick = tick - (tick % (self.chart.beat.resolution / 2));
             if let Some(cursor) = &mut self.cursor_object {
                 cursor.drag_end(
                     self.screen,
@@ -1320,9 +1880,8 @@ impl MainState {
 
     fn get_clicked_data(&self, pos: Pos2) -> (f32, u32, f64) {
         let lane = self.screen.pos_to_lane(pos.x);
-        let tick = self.screen.pos_to_tick(pos.x, pos.y);
+        let tick = self.snap_tick(self.screen.pos_to_tick(pos.x, pos.y));
         let tick_f: f64 = self.screen.pos_to_tick_f(pos.x, pos.y);
-        let tick = tick - (tick % (self.chart.beat.resolution / 2));
 
         (lane, tick, tick_f)
     }
@@ -1364,6 +1923,39 @@ impl MainState {
         }
     }
 
+    /// Opens the context menu, clicking outside of the selection selects the clicked object
+    /// first so the menu entries can act on the selection.
+    pub fn secondary_clicked(&mut self, pos: Pos2) {
+        self.mouse_x = pos.x;
+        self.mouse_y = pos.y;
+        let (lane, tick, tick_f) = self.get_clicked_data(pos);
+
+        let clicked = SelectionArea::around(tick_f as u32, lane, self.chart.beat.resolution)
+            .objects(&self.chart);
+        if !clicked.iter().any(|o| self.selection.contains(o)) {
+            self.selection.clear();
+            self.selection.extend(selection::nearest(
+                &self.chart,
+                tick_f as u32,
+                lane,
+                &self.layer_locks,
+            ));
+            self.actions.seal();
+        }
+
+        if let Some(cursor) = &mut self.cursor_object {
+            cursor.secondary_click(
+                self.screen,
+                tick,
+                tick_f,
+                lane,
+                &self.chart,
+                &mut self.actions,
+                pos2(pos.x, pos.y),
+            )
+        }
+    }
+
     pub fn mouse_motion_event(&mut self, pos: Pos2) {
         self.mouse_x = pos.x;
         self.mouse_y = pos.y;
@@ -1398,7 +1990,7 @@ fn open_chart_file(path: PathBuf) -> Result<Option<(kson::Chart, PathBuf)>> {
     match path.extension().and_then(OsStr::to_str).unwrap_or_default() {
         "ksh" => {
             let mut data = String::from("");
-            File::open(&path).unwrap().read_to_string(&mut data)?;
+            File::open(&path)?.read_to_string(&mut data)?;
             Ok(Some((kson::Chart::from_ksh(&data)?, path)))
         }
         "kson" => {
@@ -1409,7 +2001,7 @@ fn open_chart_file(path: PathBuf) -> Result<Option<(kson::Chart, PathBuf)>> {
         }
         "vox" => {
             let mut data = String::from("");
-            File::open(&path).unwrap().read_to_string(&mut data)?;
+            File::open(&path)?.read_to_string(&mut data)?;
             Ok(Some((kson::Chart::from_vox(&data)?, path)))
         }
 
@@ -1429,16 +2021,13 @@ fn open_chart() -> Result<Option<(kson::Chart, PathBuf)>> {
     }
 }
 
-fn save_chart_as(chart: &kson::Chart) -> Result<Option<PathBuf>> {
+fn pick_save_path() -> Result<Option<PathBuf>> {
     let dialog_result = nfd::open_save_dialog(Some("kson"), None)?;
 
     match dialog_result {
         nfd::Response::Okay(file_path) => {
             let mut path = PathBuf::from(&file_path);
             path.set_extension("kson");
-            let mut file = File::create(&path).unwrap();
-            profile_scope!("Write kson");
-            file.write_all(serde_json::to_string(&chart)?.as_bytes())?;
             Ok(Some(path))
         }
         _ => Ok(None),
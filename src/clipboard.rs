@@ -0,0 +1,528 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use kson::{Chart, GraphPoint, Interval, LaserSection, TimeSignature};
+use serde::{Deserialize, Serialize};
+
+use crate::chart_edit::ChartEdit;
+use crate::overlap;
+use crate::selection::{ObjectId, Selection};
+use crate::tools::{camera_graph, CameraPaths};
+
+/// Marks clipboard text as a chart fragment so unrelated text is never pasted into a chart.
+const FRAGMENT_FORMAT: &str = "kson-editor-fragment";
+
+/// A piece of a chart copied to the clipboard as JSON.
+///
+/// All ticks are relative to the start of the copied range and are scaled to the
+/// resolution of the chart the fragment is pasted into.
+#[derive(Serialize, Deserialize)]
+pub struct ChartFragment {
+    format: String,
+    /// Ticks per beat of the chart the fragment was copied from.
+    resolution: u32,
+    bt: Vec<(usize, Interval)>,
+    fx: Vec<(usize, Interval)>,
+    laser: Vec<(usize, LaserSection)>,
+    bpm: Vec<(u32, f64)>,
+    time_sig: Vec<(u32, TimeSignature)>,
+    camera: Vec<(CameraPaths, GraphPoint)>,
+}
+
+/// Camera points have no lane so they can't be selected, every camera point in the
+/// selected tick range is included instead.
+fn camera_points_in_range(
+    chart: &Chart,
+    (start, end): (u32, u32),
+) -> Vec<(CameraPaths, GraphPoint)> {
+    CameraPaths::ALL
+        .iter()
+        .flat_map(|&path| {
+            camera_graph(chart, path)
+                .iter()
+                .filter(move |p| (start..=end).contains(&p.y))
+                .map(move |p| (path, *p))
+        })
+        .collect()
+}
+
+fn measure_at_tick(chart: &Chart, tick: u32) -> Option<u32> {
+    let mut measure = 0;
+    loop {
+        let measure_tick = chart.measure_to_tick(measure);
+        if measure_tick >= tick {
+            return Some(measure).filter(|_| measure_tick == tick);
+        }
+        measure += 1;
+    }
+}
+
+impl ChartFragment {
+    /// Copies the selected objects, `None` if nothing is selected.
+    ///
+    /// Laser points selected without their whole section are copied as a new section
+    /// made of just those points when there are at least two of them.
+    pub fn from_selection(chart: &Chart, selection: &Selection) -> Option<Self> {
+        let range = selection.tick_range(chart)?;
+        let start = range.0;
+
+        let mut fragment = ChartFragment {
+            format: FRAGMENT_FORMAT.to_string(),
+            resolution: chart.beat.resolution,
+            bt: Vec::new(),
+            fx: Vec::new(),
+            laser: Vec::new(),
+            bpm: Vec::new(),
+            time_sig: Vec::new(),
+            camera: camera_points_in_range(chart, range),
+        };
+
+        let mut laser_points: BTreeMap<(usize, u32), Vec<u32>> = BTreeMap::new();
+        for object in selection.iter() {
+            match *object {
+                ObjectId::Interval { fx, lane, y } => {
+                    let notes = if fx {
+                        &chart.note.fx[lane]
+                    } else {
+                        &chart.note.bt[lane]
+                    };
+                    if let Ok(index) = notes.binary_search_by_key(&y, |n| n.y) {
+                        let target = if fx {
+                            &mut fragment.fx
+                        } else {
+                            &mut fragment.bt
+                        };
+                        target.push((lane, notes[index]));
+                    }
+                }
+                ObjectId::LaserPoint { side, section, ry } => {
+                    laser_points.entry((side, section)).or_default().push(ry)
+                }
+                ObjectId::Bpm { tick } => {
+                    if let Ok(index) = chart.beat.bpm.binary_search_by_key(&tick, |b| b.0) {
+                        fragment.bpm.push(chart.beat.bpm[index]);
+                    }
+                }
+                ObjectId::TimeSig { measure } => {
+                    if let Ok(index) = chart.beat.time_sig.binary_search_by_key(&measure, |t| t.0) {
+                        let time_sig = chart.beat.time_sig[index].1;
+                        fragment
+                            .time_sig
+                            .push((chart.measure_to_tick(measure), time_sig));
+                    }
+                }
+                // Whole sections are handled together with their points below
+                ObjectId::LaserSection { .. } => {}
+            }
+        }
+
+        for ((side, section_tick), rys) in laser_points {
+            let section = match chart.note.laser[side].binary_search_by_key(&section_tick, |s| s.0)
+            {
+                Ok(index) => &chart.note.laser[side][index],
+                Err(_) => continue,
+            };
+
+            let mut section = section.clone();
+            if !selection.contains(&ObjectId::LaserSection {
+                side,
+                tick: section_tick,
+            }) {
+                section.1.retain(|p| rys.contains(&p.ry));
+                if section.1.len() < 2 {
+                    continue;
+                }
+                let first = section.1[0].ry;
+                section.0 += first;
+                for point in &mut section.1 {
+                    point.ry -= first;
+                }
+            }
+            fragment.laser.push((side, section));
+        }
+
+        for (_, note) in fragment.bt.iter_mut().chain(fragment.fx.iter_mut()) {
+            note.y -= start;
+        }
+        for (_, section) in &mut fragment.laser {
+            section.0 -= start;
+        }
+        for (tick, _) in &mut fragment.bpm {
+            *tick -= start;
+        }
+        for (tick, _) in &mut fragment.time_sig {
+            *tick -= start;
+        }
+        for (_, point) in &mut fragment.camera {
+            point.y -= start;
+        }
+
+        Some(fragment)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        let fragment: ChartFragment = serde_json::from_str(text)?;
+        if fragment.format != FRAGMENT_FORMAT {
+            bail!("Unknown clipboard format: {}", fragment.format);
+        }
+        if fragment.resolution == 0 {
+            bail!("Invalid chart fragment resolution");
+        }
+        Ok(fragment)
+    }
+
+    /// The edit that pastes the fragment with its start at `tick`, along with the pasted
+    /// objects. `None` if there is nothing to paste.
+    ///
+    /// Notes and laser sections that would overlap one already in the chart are
+    /// skipped, BPM and time signature changes replace the existing values instead. Time
+    /// signature changes that don't land on the start of a measure are skipped.
+    pub fn paste(&self, chart: &Chart, tick: u32) -> Option<(ChartEdit, Vec<ObjectId>)> {
+        let resolution = chart.beat.resolution;
+        let scale = |t: u32| (t as u64 * resolution as u64 / self.resolution as u64) as u32;
+        let at = |t: u32| tick + scale(t);
+
+        let mut edits = Vec::new();
+        let mut pasted = Vec::new();
+
+        for &(fx, notes) in [(false, &self.bt), (true, &self.fx)].iter() {
+            for &(lane, note) in notes {
+                let interval = Interval {
+                    y: at(note.y),
+                    l: scale(note.l),
+                };
+                if overlap::interval_fits(chart, fx, lane, &interval, None) {
+                    edits.push(ChartEdit::AddInterval { fx, lane, interval });
+                    pasted.push(ObjectId::Interval {
+                        fx,
+                        lane,
+                        y: interval.y,
+                    });
+                }
+            }
+        }
+
+        for (side, section) in &self.laser {
+            let mut section = section.clone();
+            section.0 = at(section.0);
+            for point in &mut section.1 {
+                point.ry = scale(point.ry);
+            }
+
+            if overlap::laser_section_fits(chart, *side, &section, None) {
+                pasted.push(ObjectId::LaserSection {
+                    side: *side,
+                    tick: section.0,
+                });
+                pasted.extend(section.1.iter().map(|p| ObjectId::LaserPoint {
+                    side: *side,
+                    section: section.0,
+                    ry: p.ry,
+                }));
+                edits.push(ChartEdit::AddLaserSection {
+                    side: *side,
+                    section,
+                });
+            }
+        }
+
+        for &(bpm_tick, bpm) in &self.bpm {
+            let tick = at(bpm_tick);
+            if chart.beat.bpm.binary_search_by_key(&tick, |b| b.0).is_ok() {
+                edits.push(ChartEdit::EditBpm { tick, bpm });
+            } else {
+                edits.push(ChartEdit::AddBpm { tick, bpm });
+            }
+            pasted.push(ObjectId::Bpm { tick });
+        }
+
+        for &(ts_tick, time_sig) in &self.time_sig {
+            let measure = match measure_at_tick(chart, at(ts_tick)) {
+                Some(measure) => measure,
+                None => continue,
+            };
+            if chart
+                .beat
+                .time_sig
+                .binary_search_by_key(&measure, |t| t.0)
+                .is_ok()
+            {
+                edits.push(ChartEdit::EditTimeSig { measure, time_sig });
+            } else {
+                edits.push(ChartEdit::AddTimeSig { measure, time_sig });
+            }
+            pasted.push(ObjectId::TimeSig { measure });
+        }
+
+        for &(graph, point) in &self.camera {
+            let point = GraphPoint {
+                y: at(point.y),
+                ..point
+            };
+            if camera_graph(chart, graph)
+                .binary_search_by_key(&point.y, |p| p.y)
+                .is_err()
+            {
+                edits.push(ChartEdit::AddCameraPoint { graph, point });
+            }
+        }
+
+        if edits.is_empty() {
+            None
+        } else {
+            Some((ChartEdit::Batch(edits), pasted))
+        }
+    }
+}
+
+/// Edits removing the selected objects. Laser sections are only removed when they are
+/// selected completely, the BPM and time signature at the start of the chart are kept.
+fn removals(selection: &Selection) -> Vec<ChartEdit> {
+    selection
+        .iter()
+        .filter_map(|object| match *object {
+            ObjectId::Interval { fx, lane, y } => Some(ChartEdit::RemoveInterval { fx, lane, y }),
+            ObjectId::LaserSection { side, tick } => {
+                Some(ChartEdit::RemoveLaserSection { side, tick })
+            }
+            ObjectId::LaserPoint { .. } => None,
+            ObjectId::Bpm { tick } if tick > 0 => Some(ChartEdit::RemoveBpm { tick }),
+            ObjectId::TimeSig { measure } if measure > 0 => {
+                Some(ChartEdit::RemoveTimeSig { measure })
+            }
+            ObjectId::Bpm { .. } | ObjectId::TimeSig { .. } => None,
+        })
+        .collect()
+}
+
+/// The edit that removes the selected objects and nothing else.
+pub fn delete(selection: &Selection) -> Option<ChartEdit> {
+    let edits = removals(selection);
+    if edits.is_empty() {
+        None
+    } else {
+        Some(ChartEdit::Batch(edits))
+    }
+}
+
+/// The edit that removes the selected objects after they have been copied, along with the
+/// camera points in their range which are copied with them.
+pub fn cut(chart: &Chart, selection: &Selection) -> Option<ChartEdit> {
+    let mut edits = removals(selection);
+
+    if let Some(range) = selection.tick_range(chart) {
+        edits.extend(
+            camera_points_in_range(chart, range)
+                .into_iter()
+                .map(|(graph, p)| ChartEdit::RemoveCameraPoint { graph, y: p.y }),
+        );
+    }
+
+    if edits.is_empty() {
+        None
+    } else {
+        Some(ChartEdit::Batch(edits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action_stack::Document;
+    use crate::chart_edit::tests::{camera_point, chart, notes, section, sections, time_sigs};
+
+    fn source_chart() -> Chart {
+        let mut chart = chart();
+        chart.note.bt[1].push(Interval { y: 960, l: 240 });
+        chart.note.fx[0].push(Interval { y: 1200, l: 0 });
+        chart.note.laser[0].push(section(960, &[(0, 0.0), (240, 1.0)]));
+        chart.beat.bpm.push((1200, 180.0));
+        chart.beat.time_sig.push((2, TimeSignature(3, 4)));
+        chart.camera.cam.body.zoom.push(camera_point(1000, 1.0));
+        chart
+    }
+
+    fn selection() -> Selection {
+        let mut selection = Selection::default();
+        selection.extend(vec![
+            ObjectId::Interval {
+                fx: false,
+                lane: 1,
+                y: 960,
+            },
+            ObjectId::Interval {
+                fx: true,
+                lane: 0,
+                y: 1200,
+            },
+            ObjectId::LaserSection { side: 0, tick: 960 },
+            ObjectId::LaserPoint {
+                side: 0,
+                section: 960,
+                ry: 0,
+            },
+            ObjectId::LaserPoint {
+                side: 0,
+                section: 960,
+                ry: 240,
+            },
+            ObjectId::Bpm { tick: 1200 },
+            ObjectId::TimeSig { measure: 2 },
+        ]);
+        selection
+    }
+
+    fn copy() -> ChartFragment {
+        let fragment = ChartFragment::from_selection(&source_chart(), &selection()).unwrap();
+        ChartFragment::from_json(&fragment.to_json().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn copy_is_relative_to_the_selection() {
+        let fragment = copy();
+
+        assert_eq!(fragment.resolution, 240);
+        assert_eq!(fragment.bt.len(), 1);
+        assert_eq!((fragment.bt[0].1.y, fragment.bt[0].1.l), (0, 240));
+        assert_eq!(fragment.fx[0].1.y, 240);
+        assert_eq!(fragment.laser[0].1 .0, 0);
+        assert_eq!(fragment.bpm, vec![(240, 180.0)]);
+        assert_eq!(fragment.time_sig.len(), 1);
+        assert_eq!(fragment.time_sig[0].0, 960);
+        assert_eq!(fragment.camera.len(), 1);
+        assert_eq!(fragment.camera[0].1.y, 40);
+    }
+
+    #[test]
+    fn copy_part_of_a_laser_section() {
+        let mut chart = source_chart();
+        chart.note.laser[0][0] = section(960, &[(0, 0.0), (120, 0.5), (240, 1.0)]);
+        let mut selection = Selection::default();
+        selection.extend(vec![
+            ObjectId::LaserPoint {
+                side: 0,
+                section: 960,
+                ry: 120,
+            },
+            ObjectId::LaserPoint {
+                side: 0,
+                section: 960,
+                ry: 240,
+            },
+        ]);
+
+        let fragment = ChartFragment::from_selection(&chart, &selection).unwrap();
+        let laser = &fragment.laser[0].1;
+        assert_eq!(laser.0, 0);
+        assert_eq!(
+            laser.1.iter().map(|p| (p.ry, p.v)).collect::<Vec<_>>(),
+            vec![(0, 0.5), (120, 1.0)]
+        );
+    }
+
+    #[test]
+    fn from_json_rejects_other_text() {
+        let json = copy().to_json().unwrap();
+
+        assert!(ChartFragment::from_json("").is_err());
+        assert!(ChartFragment::from_json("{}").is_err());
+        assert!(ChartFragment::from_json(&json.replace(FRAGMENT_FORMAT, "other")).is_err());
+        assert!(
+            ChartFragment::from_json(&json.replace("\"resolution\":240", "\"resolution\":0"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn paste() {
+        let mut chart = chart();
+        let (edit, pasted) = copy().paste(&chart, 1920).unwrap();
+        chart.apply(&edit).unwrap();
+
+        assert_eq!(notes(&chart, false, 1), vec![(1920, 240)]);
+        assert_eq!(notes(&chart, true, 0), vec![(2160, 0)]);
+        assert_eq!(
+            sections(&chart, 0),
+            vec![(1920, vec![(0, 0.0), (240, 1.0)])]
+        );
+        assert_eq!(chart.beat.bpm, vec![(0, 120.0), (2160, 180.0)]);
+        assert_eq!(time_sigs(&chart), vec![(0, 4, 4), (3, 3, 4)]);
+        assert_eq!(
+            camera_graph(&chart, CameraPaths::Zoom)
+                .iter()
+                .map(|p| p.y)
+                .collect::<Vec<_>>(),
+            vec![1960]
+        );
+        assert_eq!(pasted.len(), 7);
+        assert!(pasted.iter().all(|o| o.exists(&chart)));
+    }
+
+    #[test]
+    fn paste_scales_to_the_resolution() {
+        let mut chart = chart();
+        chart.beat.resolution = 480;
+        let (edit, _) = copy().paste(&chart, 0).unwrap();
+        chart.apply(&edit).unwrap();
+
+        assert_eq!(notes(&chart, false, 1), vec![(0, 480)]);
+        assert_eq!(notes(&chart, true, 0), vec![(480, 0)]);
+        assert_eq!(sections(&chart, 0), vec![(0, vec![(0, 0.0), (480, 1.0)])]);
+        assert_eq!(time_sigs(&chart), vec![(0, 4, 4), (1, 3, 4)]);
+    }
+
+    #[test]
+    fn paste_over_existing_objects() {
+        let mut chart = chart();
+        chart.note.bt[1].push(Interval { y: 2000, l: 0 });
+        chart.beat.bpm.push((2160, 60.0));
+        let (edit, pasted) = copy().paste(&chart, 1920).unwrap();
+        chart.apply(&edit).unwrap();
+
+        assert_eq!(notes(&chart, false, 1), vec![(2000, 0)]);
+        assert_eq!(chart.beat.bpm, vec![(0, 120.0), (2160, 180.0)]);
+        assert!(!pasted.contains(&ObjectId::Interval {
+            fx: false,
+            lane: 1,
+            y: 1920,
+        }));
+    }
+
+    #[test]
+    fn paste_skips_time_signatures_that_do_not_start_a_measure() {
+        let mut chart = chart();
+        let (edit, pasted) = copy().paste(&chart, 100).unwrap();
+        chart.apply(&edit).unwrap();
+        assert_eq!(time_sigs(&chart), vec![(0, 4, 4)]);
+        assert_eq!(pasted.len(), 6);
+    }
+
+    #[test]
+    fn delete_and_cut() {
+        let mut chart = source_chart();
+        let mut selection = selection();
+        selection.extend(vec![ObjectId::Bpm { tick: 0 }]);
+
+        let mut deleted = chart.clone();
+        deleted.apply(&delete(&selection).unwrap()).unwrap();
+        assert!(notes(&deleted, false, 1).is_empty());
+        assert!(deleted.note.laser[0].is_empty());
+        assert_eq!(deleted.beat.bpm, vec![(0, 120.0)]);
+        assert_eq!(time_sigs(&deleted), vec![(0, 4, 4)]);
+        assert_eq!(camera_graph(&deleted, CameraPaths::Zoom).len(), 1);
+
+        chart.apply(&cut(&chart, &selection).unwrap()).unwrap();
+        assert!(camera_graph(&chart, CameraPaths::Zoom).is_empty());
+
+        let mut start = Selection::default();
+        start.extend(vec![
+            ObjectId::Bpm { tick: 0 },
+            ObjectId::TimeSig { measure: 0 },
+        ]);
+        assert!(delete(&start).is_none());
+    }
+}
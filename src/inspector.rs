@@ -0,0 +1,561 @@
+use eframe::egui::{Checkbox, Color32, DragValue, Grid, TextEdit, Ui};
+use kson::{Chart, GraphSectionPoint, Interval, LaserSection};
+
+use crate::chart_edit::ChartEdit;
+use crate::chart_editor::MainState;
+use crate::i18n;
+use crate::overlap;
+use crate::selection::{self, ObjectId};
+use crate::tools::{camera_graph, lane_name, laser_side_name, CameraPaths};
+
+/// Ticks in a beat of `measure`, following its time signature.
+fn beat_ticks(chart: &Chart, measure: u32) -> u32 {
+    let denominator = chart
+        .beat
+        .time_sig
+        .iter()
+        .take_while(|t| t.0 <= measure)
+        .last()
+        .map_or(4, |t| t.1 .1);
+    (chart.beat.resolution * 4 / denominator.max(1)).max(1)
+}
+
+/// Formats a tick as `measure:beat:tick`, measures and beats are counted from 1.
+pub fn format_position(chart: &Chart, tick: u32) -> String {
+    let measure = chart.tick_to_measure(tick);
+    let offset = tick - chart.measure_to_tick(measure);
+    let beat = beat_ticks(chart, measure);
+    format!("{}:{}:{}", measure + 1, offset / beat + 1, offset % beat)
+}
+
+/// Parses `measure:beat:tick`, `measure:beat` or `measure` into a tick. Positions past the
+/// end of their measure are rejected.
+pub fn parse_position(chart: &Chart, text: &str) -> Option<u32> {
+    let mut parts = text.trim().split(':').map(|p| p.trim().parse::<u32>().ok());
+    let measure = parts.next()??.checked_sub(1)?;
+    let beat = match parts.next() {
+        Some(beat) => beat?.checked_sub(1)?,
+        None => 0,
+    };
+    let tick = match parts.next() {
+        Some(tick) => tick?,
+        None => 0,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let start = chart.measure_to_tick(measure);
+    let offset = beat
+        .checked_mul(beat_ticks(chart, measure))?
+        .checked_add(tick)?;
+    if offset >= chart.measure_to_tick(measure + 1) - start {
+        return None;
+    }
+    Some(start + offset)
+}
+
+fn interval(chart: &Chart, fx: bool, lane: usize, y: u32) -> Option<Interval> {
+    let notes = if fx {
+        chart.note.fx.get(lane)?
+    } else {
+        chart.note.bt.get(lane)?
+    };
+    let index = notes.binary_search_by_key(&y, |n| n.y).ok()?;
+    Some(notes[index])
+}
+
+fn laser_section(chart: &Chart, side: usize, tick: u32) -> Option<&LaserSection> {
+    let sections = chart.note.laser.get(side)?;
+    let index = sections.binary_search_by_key(&tick, |s| s.tick()).ok()?;
+    Some(&sections[index])
+}
+
+/// An edit made in the inspector, applied once the panel is done borrowing the chart.
+struct Edit {
+    description: String,
+    /// Continuous edits to the same field are merged into one action
+    merge_key: Option<String>,
+    edit: ChartEdit,
+    /// New id of the inspected object if the edit moved it
+    moved: Option<ObjectId>,
+}
+
+/// Side panel that shows the fields of the selected or hovered object as editable numbers.
+#[derive(Default)]
+pub struct Inspector {
+    /// Object under the mouse the last time it was over the chart
+    hovered: Option<ObjectId>,
+    /// Position being typed for an object, committed when the field loses focus
+    position_text: Option<(ObjectId, String)>,
+}
+
+impl Inspector {
+    /// Picks up the object under the mouse, called while the mouse is over the chart.
+    pub fn hover(&mut self, state: &MainState) {
+        self.hovered = selection::nearest(
+            &state.chart,
+            state.get_cursor_tick_from_mouse(),
+            state.get_cursor_lane_from_mouse(),
+            &state.layer_locks,
+        );
+    }
+
+    /// The only selected object, a selected laser section counts as one object together
+    /// with its points. Falls back to the hovered object.
+    fn target(&self, state: &MainState) -> Option<ObjectId> {
+        let selection = &state.selection;
+        let mut selected = selection.iter().filter(|o| match **o {
+            ObjectId::LaserPoint { side, section, .. } => {
+                !selection.contains(&ObjectId::LaserSection {
+                    side,
+                    tick: section,
+                })
+            }
+            _ => true,
+        });
+
+        match (selected.next(), selected.next()) {
+            (Some(object), None) => Some(*object),
+            _ => self.hovered.filter(|o| o.exists(&state.chart)),
+        }
+    }
+
+    /// A `measure:beat:tick` field, returns the new tick once a different valid position
+    /// has been entered. Positions for which `fits` is false are shown as taken.
+    fn position_field(
+        &mut self,
+        ui: &mut Ui,
+        chart: &Chart,
+        id: ObjectId,
+        tick: u32,
+        fits: impl Fn(u32) -> bool,
+    ) -> Option<u32> {
+        ui.label(i18n::fl!("position"));
+        let formatted = format_position(chart, tick);
+        if matches!(&self.position_text, Some((editing, _)) if *editing != id) {
+            self.position_text = None;
+        }
+        let (_, text) = self
+            .position_text
+            .get_or_insert_with(|| (id, formatted.clone()));
+
+        let parsed = parse_position(chart, text);
+        let valid = parsed.filter(|new_tick| *new_tick == tick || fits(*new_tick));
+        let mut field = TextEdit::singleline(text).desired_width(90.0);
+        if valid.is_none() {
+            field = field.text_color(Color32::RED);
+        }
+        let response = ui.add(field);
+        let response = match (parsed, valid) {
+            (None, _) => response.on_hover_text(i18n::fl!("invalid_position")),
+            (Some(_), None) => response.on_hover_text(i18n::fl!("position_taken")),
+            _ => response,
+        };
+        ui.end_row();
+
+        if response.lost_focus() {
+            self.position_text = None;
+            valid.filter(|new_tick| *new_tick != tick)
+        } else {
+            if !response.has_focus() && *text != formatted {
+                // Changed by something else, an undo for example
+                *text = formatted;
+            }
+            None
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, state: &mut MainState) {
+        let target = self.target(state);
+        if matches!(&self.position_text, Some((id, _)) if Some(*id) != target) {
+            self.position_text = None;
+        }
+
+        let mut edits = Vec::new();
+        Grid::new("inspector")
+            .num_columns(2)
+            .show(ui, |ui| match target {
+                Some(id) => self.object_ui(ui, &state.chart, id, &mut edits),
+                None => {
+                    ui.label(i18n::fl!("nothing_to_inspect"));
+                    ui.end_row();
+                }
+            });
+
+        ui.separator();
+        ui.label(i18n::fl!("camera_at_cursor"));
+        Grid::new("inspector_camera").num_columns(2).show(ui, |ui| {
+            Self::camera_ui(ui, &state.chart, state.cursor_line, &mut edits);
+        });
+
+        for edit in edits {
+            match edit.merge_key {
+                Some(key) => state
+                    .actions
+                    .new_merging_action(&key, edit.description, edit.edit),
+                None => state.actions.new_action(edit.description, edit.edit),
+            }
+            if let (Some(id), Some(moved)) = (target, edit.moved) {
+                if state.selection.contains(&id) {
+                    state.selection.replace(&[(id, moved)]);
+                }
+                if self.hovered == Some(id) {
+                    self.hovered = Some(moved);
+                }
+            }
+        }
+    }
+
+    fn object_ui(&mut self, ui: &mut Ui, chart: &Chart, id: ObjectId, edits: &mut Vec<Edit>) {
+        let merge_key = |field: &str| Some(format!("inspector:{:?}:{}", id, field));
+
+        match id {
+            ObjectId::Interval { fx, lane, y } => {
+                let interval = match interval(chart, fx, lane, y) {
+                    Some(interval) => interval,
+                    None => return,
+                };
+                ui.label(lane_name(fx, lane));
+                ui.end_row();
+
+                let fits = |tick: u32| {
+                    let moved = Interval {
+                        y: tick,
+                        l: interval.l,
+                    };
+                    overlap::interval_fits(chart, fx, lane, &moved, Some(y))
+                };
+                if let Some(tick) = self.position_field(ui, chart, id, y, fits) {
+                    // Moving can pass other notes, so it goes through a remove and an add
+                    // which keep the lane sorted
+                    let moved = Interval {
+                        y: tick,
+                        l: interval.l,
+                    };
+                    edits.push(Edit {
+                        description: i18n::fl!("move_objects"),
+                        merge_key: None,
+                        edit: ChartEdit::Batch(vec![
+                            ChartEdit::RemoveInterval { fx, lane, y },
+                            ChartEdit::AddInterval {
+                                fx,
+                                lane,
+                                interval: moved,
+                            },
+                        ]),
+                        moved: Some(ObjectId::Interval { fx, lane, y: tick }),
+                    });
+                }
+
+                // The note has to end before the next one starts. A note that already
+                // reaches it is left as it is, the field would shorten it just by showing it
+                let notes = if fx {
+                    &chart.note.fx[lane]
+                } else {
+                    &chart.note.bt[lane]
+                };
+                let max_length = notes
+                    .iter()
+                    .find(|n| n.y > y)
+                    .map_or(u32::MAX, |next| (next.y - y - 1).max(interval.l));
+                let mut length = interval.l;
+                ui.label(i18n::fl!("length"));
+                ui.add(
+                    DragValue::new(&mut length)
+                        .speed(1.0)
+                        .clamp_range(0..=max_length),
+                );
+                ui.end_row();
+
+                if length != interval.l {
+                    edits.push(Edit {
+                        description: i18n::fl!("resize_note", lane = if fx { "FX" } else { "BT" }),
+                        merge_key: merge_key("length"),
+                        edit: ChartEdit::ResizeInterval {
+                            fx,
+                            lane,
+                            y,
+                            interval: Interval { y, l: length },
+                        },
+                        moved: None,
+                    });
+                }
+            }
+            ObjectId::LaserSection { side, tick } => {
+                let section = match laser_section(chart, side, tick) {
+                    Some(section) => section,
+                    None => return,
+                };
+                ui.label(i18n::fl!("laser_section", side = laser_side_name(side)));
+                ui.label(i18n::fl!("laser_point_count", count = section.1.len()));
+                ui.end_row();
+
+                let fits = |new_tick: u32| {
+                    let mut moved = section.clone();
+                    moved.0 = new_tick;
+                    overlap::laser_section_fits(chart, side, &moved, Some(tick))
+                };
+                if let Some(new_tick) = self.position_field(ui, chart, id, tick, fits) {
+                    let mut moved = section.clone();
+                    moved.0 = new_tick;
+                    edits.push(Edit {
+                        description: i18n::fl!("move_objects"),
+                        merge_key: None,
+                        edit: ChartEdit::Batch(vec![
+                            ChartEdit::RemoveLaserSection { side, tick },
+                            ChartEdit::AddLaserSection {
+                                side,
+                                section: moved,
+                            },
+                        ]),
+                        moved: Some(ObjectId::LaserSection {
+                            side,
+                            tick: new_tick,
+                        }),
+                    });
+                }
+
+                let mut wide = section.wide() == 2;
+                ui.label("");
+                if ui
+                    .add(Checkbox::new(&mut wide, i18n::fl!("wide_laser")))
+                    .changed()
+                {
+                    edits.push(Edit {
+                        description: i18n::fl!("toggle_laser_wide"),
+                        merge_key: None,
+                        edit: ChartEdit::SetLaserWide {
+                            side,
+                            section: tick,
+                            wide: if wide { 2 } else { 1 },
+                        },
+                        moved: None,
+                    });
+                }
+                ui.end_row();
+            }
+            ObjectId::LaserPoint { side, section, ry } => {
+                let laser = match laser_section(chart, side, section) {
+                    Some(laser) => laser,
+                    None => return,
+                };
+                let index = match laser.1.binary_search_by_key(&ry, |p| p.ry) {
+                    Ok(index) => index,
+                    Err(_) => return,
+                };
+                let point = laser.1[index];
+                ui.label(i18n::fl!("laser_point", side = laser_side_name(side)));
+                ui.end_row();
+
+                let mut new = point;
+                if index == 0 {
+                    // The first point is where the section starts, move the section instead
+                    ui.label(i18n::fl!("position"));
+                    ui.label(format_position(chart, section + ry));
+                    ui.end_row();
+                } else {
+                    let fits = |tick: u32| {
+                        let after_previous = tick > section + laser.1[index - 1].ry;
+                        let before_next = laser
+                            .1
+                            .get(index + 1)
+                            .map_or(true, |next| tick < section + next.ry);
+                        after_previous && before_next
+                    };
+                    if let Some(tick) = self.position_field(ui, chart, id, section + ry, fits) {
+                        new.ry = tick - section;
+                    }
+                }
+
+                let value_field = |ui: &mut Ui, label: String, value: &mut f64| {
+                    ui.label(label);
+                    ui.add(DragValue::new(value).speed(0.01).clamp_range(0.0..=1.0));
+                    ui.end_row();
+                };
+                value_field(ui, i18n::fl!("value"), &mut new.v);
+
+                let mut slam = new.vf.is_some();
+                ui.label("");
+                ui.checkbox(&mut slam, i18n::fl!("slam"));
+                ui.end_row();
+                new.vf = match (slam, new.vf) {
+                    (true, vf) => Some(vf.unwrap_or(new.v)),
+                    (false, _) => None,
+                };
+                if let Some(vf) = &mut new.vf {
+                    value_field(ui, i18n::fl!("slam_value"), vf);
+                }
+
+                if index + 1 < laser.1.len() {
+                    let mut a = new.a.unwrap_or(0.5);
+                    let mut b = new.b.unwrap_or(0.5);
+                    value_field(ui, i18n::fl!("curve_a"), &mut a);
+                    value_field(ui, i18n::fl!("curve_b"), &mut b);
+                    if new.a.is_some() || a != 0.5 || b != 0.5 {
+                        new.a = Some(a);
+                        new.b = Some(b);
+                    }
+                }
+
+                if !same_point(&new, &point) {
+                    let moved = new.ry != point.ry;
+                    edits.push(Edit {
+                        description: if moved {
+                            i18n::fl!("move_laser_point", side = laser_side_name(side))
+                        } else {
+                            i18n::fl!("edit_laser_point", side = laser_side_name(side))
+                        },
+                        merge_key: if moved { None } else { merge_key("point") },
+                        edit: ChartEdit::SetLaserPoint {
+                            side,
+                            section,
+                            ry,
+                            point: new,
+                        },
+                        moved: Some(ObjectId::LaserPoint {
+                            side,
+                            section,
+                            ry: new.ry,
+                        }),
+                    });
+                }
+            }
+            ObjectId::Bpm { tick } => {
+                let bpm = match chart.beat.bpm.binary_search_by_key(&tick, |b| b.0) {
+                    Ok(index) => chart.beat.bpm[index].1,
+                    Err(_) => return,
+                };
+
+                if tick == 0 {
+                    ui.label(i18n::fl!("position"));
+                    ui.label(format_position(chart, tick));
+                    ui.end_row();
+                } else if let Some(new_tick) =
+                    self.position_field(ui, chart, id, tick, |new_tick| {
+                        chart
+                            .beat
+                            .bpm
+                            .binary_search_by_key(&new_tick, |b| b.0)
+                            .is_err()
+                    })
+                {
+                    edits.push(Edit {
+                        description: i18n::fl!("edit_bpm_change"),
+                        merge_key: None,
+                        edit: ChartEdit::Batch(vec![
+                            ChartEdit::RemoveBpm { tick },
+                            ChartEdit::AddBpm {
+                                tick: new_tick,
+                                bpm,
+                            },
+                        ]),
+                        moved: Some(ObjectId::Bpm { tick: new_tick }),
+                    });
+                }
+
+                let mut new_bpm = bpm;
+                ui.label("BPM");
+                ui.add(
+                    DragValue::new(&mut new_bpm)
+                        .speed(0.1)
+                        .clamp_range(0.01..=f64::MAX),
+                );
+                ui.end_row();
+                if new_bpm != bpm {
+                    edits.push(Edit {
+                        description: i18n::fl!("edit_bpm_change"),
+                        merge_key: merge_key("bpm"),
+                        edit: ChartEdit::EditBpm { tick, bpm: new_bpm },
+                        moved: None,
+                    });
+                }
+            }
+            ObjectId::TimeSig { measure } => {
+                let time_sig = match chart.beat.time_sig.binary_search_by_key(&measure, |t| t.0) {
+                    Ok(index) => chart.beat.time_sig[index].1,
+                    Err(_) => return,
+                };
+                ui.label(i18n::fl!("measure"));
+                ui.label((measure + 1).to_string());
+                ui.end_row();
+
+                let mut new = time_sig;
+                ui.label(i18n::fl!("change_time_signature"));
+                ui.horizontal(|ui| {
+                    ui.add(DragValue::new(&mut new.0).speed(0.2).clamp_range(1..=99));
+                    ui.label("/");
+                    ui.add(DragValue::new(&mut new.1).speed(0.2).clamp_range(1..=64));
+                });
+                ui.end_row();
+
+                if new.0 != time_sig.0 || new.1 != time_sig.1 {
+                    edits.push(Edit {
+                        description: i18n::fl!("edit_time_signature_change"),
+                        merge_key: merge_key("time_sig"),
+                        edit: ChartEdit::EditTimeSig {
+                            measure,
+                            time_sig: new,
+                        },
+                        moved: None,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Values and curves of the camera points exactly at the cursor line.
+    fn camera_ui(ui: &mut Ui, chart: &Chart, y: u32, edits: &mut Vec<Edit>) {
+        let mut any = false;
+        for graph in CameraPaths::ALL {
+            let points = camera_graph(chart, graph);
+            let index = match points.binary_search_by_key(&y, |p| p.y) {
+                Ok(index) => index,
+                Err(_) => continue,
+            };
+            any = true;
+            let point = points[index];
+            let merge_key = |field: &str| format!("inspector:{:?}:{}:{}", graph, y, field);
+
+            let mut v = point.v;
+            ui.label(graph.to_string());
+            ui.add(DragValue::new(&mut v).speed(0.01).clamp_range(-3.0..=3.0));
+            ui.end_row();
+            if v != point.v {
+                edits.push(Edit {
+                    description: i18n::fl!("edit_camera_value", graph = graph.to_string()),
+                    merge_key: Some(merge_key("value")),
+                    edit: ChartEdit::SetCameraValue { graph, y, v },
+                    moved: None,
+                });
+            }
+
+            if index + 1 < points.len() {
+                let (mut a, mut b) = (point.a.unwrap_or(0.5), point.b.unwrap_or(0.5));
+                ui.label(i18n::fl!("curve"));
+                ui.horizontal(|ui| {
+                    ui.add(DragValue::new(&mut a).speed(0.01).clamp_range(0.0..=1.0));
+                    ui.add(DragValue::new(&mut b).speed(0.01).clamp_range(0.0..=1.0));
+                });
+                ui.end_row();
+                if a != point.a.unwrap_or(0.5) || b != point.b.unwrap_or(0.5) {
+                    edits.push(Edit {
+                        description: i18n::fl!("edit_curve_for_camera", graph = graph.to_string()),
+                        merge_key: Some(merge_key("curve")),
+                        edit: ChartEdit::SetCameraCurve { graph, y, a, b },
+                        moved: None,
+                    });
+                }
+            }
+        }
+
+        if !any {
+            ui.label(i18n::fl!("no_camera_points_at_cursor"));
+            ui.end_row();
+        }
+    }
+}
+
+fn same_point(a: &GraphSectionPoint, b: &GraphSectionPoint) -> bool {
+    a.ry == b.ry && a.v == b.v && a.vf == b.vf && a.a == b.a && a.b == b.b
+}
@@ -1,10 +1,11 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use anyhow::Result;
+use chart_edit::ChartEdit;
 use chart_editor::MainState;
 use eframe::egui::style::Selection;
 use eframe::egui::{
@@ -13,17 +14,27 @@ use eframe::egui::{
 };
 use eframe::App;
 use i18n_embed::unic_langid::LanguageIdentifier;
-use kson::{BgmInfo, Chart, MetaInfo};
+use kson::{BgmInfo, MetaInfo};
 use puffin::profile_scope;
 use serde::{Deserialize, Serialize};
 
 mod action_stack;
 mod assets;
+mod backup;
 mod camera_widget;
 mod chart_camera;
+mod chart_edit;
 mod chart_editor;
+mod clipboard;
 mod i18n;
+mod inspector;
+mod notifications;
+mod overlap;
+mod recovery;
+mod selection;
+mod snap;
 mod tools;
+mod transform;
 mod utils;
 
 pub trait Widget {
@@ -44,29 +55,31 @@ impl Widget for &mut kson::MetaInfo {
     fn ui(self, ui: &mut Ui) -> Response {
         let edit_row = |ui: &mut Ui, label: &str, data: &mut String| {
             ui.label(label);
-            ui.text_edit_singleline(data);
+            let response = ui.text_edit_singleline(data);
             ui.end_row();
+            response
         };
 
         egui::Grid::new("metadata_editor")
             .show(ui, |ui| {
-                edit_row(ui, &i18n::fl!("title"), &mut self.title);
-                edit_row(ui, &i18n::fl!("artist"), &mut self.artist);
-                edit_row(ui, &i18n::fl!("effector"), &mut self.chart_author);
-                edit_row(ui, &i18n::fl!("jacket"), &mut self.jacket_filename);
-                edit_row(ui, &i18n::fl!("jacket_artist"), &mut self.jacket_author);
+                let mut response = edit_row(ui, &i18n::fl!("title"), &mut self.title);
+                response |= edit_row(ui, &i18n::fl!("artist"), &mut self.artist);
+                response |= edit_row(ui, &i18n::fl!("effector"), &mut self.chart_author);
+                response |= edit_row(ui, &i18n::fl!("jacket"), &mut self.jacket_filename);
+                response |= edit_row(ui, &i18n::fl!("jacket_artist"), &mut self.jacket_author);
 
                 ui.label(i18n::fl!("difficulty"));
                 ui.end_row();
 
                 ui.label(i18n::fl!("level"));
-                ui.add(DragValue::new(&mut self.level).clamp_range(1..=20));
+                response |= ui.add(DragValue::new(&mut self.level).clamp_range(1..=20));
                 ui.end_row();
 
                 ui.label(i18n::fl!("index"));
-                ui.add(DragValue::new(&mut self.difficulty));
+                response |= ui.add(DragValue::new(&mut self.difficulty));
+                response
             })
-            .response
+            .inner
     }
 }
 
@@ -122,29 +135,30 @@ impl Widget for &mut kson::BgmInfo {
         Grid::new("bgm_info")
             .show(ui, |ui| {
                 ui.label(i18n::fl!("audio_file"));
-                ui.text_edit_singleline(self.filename.as_mut().unwrap());
+                let mut response = ui.text_edit_singleline(self.filename.as_mut().unwrap());
                 ui.end_row();
 
                 ui.label(i18n::fl!("offset"));
-                ui.add(DragValue::new(&mut self.offset).suffix("ms"));
+                response |= ui.add(DragValue::new(&mut self.offset).suffix("ms"));
                 ui.end_row();
 
                 ui.label(i18n::fl!("volume"));
-                ui.add(Slider::new(&mut self.vol, 0.0..=1.0).clamp_to_range(true));
+                response |= ui.add(Slider::new(&mut self.vol, 0.0..=1.0).clamp_to_range(true));
                 ui.end_row();
 
                 ui.separator();
                 ui.end_row();
 
                 ui.label(i18n::fl!("preview_offset"));
-                ui.add(DragValue::new(&mut self.preview.offset).suffix("ms"));
+                response |= ui.add(DragValue::new(&mut self.preview.offset).suffix("ms"));
                 ui.end_row();
 
                 ui.label(i18n::fl!("preview_duration"));
-                ui.add(DragValue::new(&mut self.preview.duration).suffix("ms"));
+                response |= ui.add(DragValue::new(&mut self.preview.duration).suffix("ms"));
                 ui.end_row();
+                response
             })
-            .response
+            .inner
     }
 }
 
@@ -168,6 +182,29 @@ pub enum GuiEvent {
     Previous,
     ExportKsh,
     Preferences,
+    History,
+    SelectAll,
+    ClearSelection,
+    Copy,
+    Cut,
+    SnapFiner,
+    SnapCoarser,
+    NudgeUp,
+    NudgeDown,
+    NudgeLeft,
+    NudgeRight,
+    InsertMeasures(u32),
+    DeleteMeasures(u32),
+    ToggleLaserWide,
+    #[serde(skip_serializing)]
+    Paste(String),
+    #[serde(skip_serializing)]
+    Transform {
+        transform: transform::Transform,
+        selection_only: bool,
+    },
+    #[serde(skip_serializing)]
+    RestoreBackup(PathBuf),
 }
 
 impl std::fmt::Display for GuiEvent {
@@ -190,6 +227,8 @@ pub enum ChartTool {
     BPM,
     TimeSig,
     Camera,
+    Select,
+    Eraser,
 }
 
 #[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Eq, Clone)]
@@ -207,12 +246,40 @@ pub struct Modifiers {
     pub command: bool,
 }
 
+/// Values entered in the transform menu.
+struct TransformOptions {
+    selection_only: bool,
+    shift: i64,
+    scale: (u32, u32),
+}
+
+impl Default for TransformOptions {
+    fn default() -> Self {
+        TransformOptions {
+            selection_only: false,
+            shift: 0,
+            scale: (1, 1),
+        }
+    }
+}
+
 struct AppState {
     editor: chart_editor::MainState,
     key_bindings: HashMap<KeyCombo, GuiEvent>,
     show_preferences: bool,
+    show_history: bool,
+    show_inspector: bool,
+    inspector: inspector::Inspector,
+    transform_options: TransformOptions,
+    /// Number of measures inserted or deleted from the edit menu
+    measure_count: u32,
     new_chart: Option<NewChartOptions>,
     meta_edit: Option<MetaInfo>,
+    /// New resolution entered in the resolution dialog
+    resolution_edit: Option<u32>,
+    /// Report shown in the resolution dialog, with the chart revision and resolution it
+    /// was made for
+    resolution_report: Option<(u64, u32, transform::ResolutionReport)>,
     bgm_edit: Option<BgmInfo>,
     exiting: bool,
     language: LanguageIdentifier,
@@ -224,6 +291,16 @@ struct Config {
     track_width: f32,
     beats_per_column: u32,
     language: LanguageIdentifier,
+    #[serde(default = "default_backup_count")]
+    backup_count: usize,
+    #[serde(default)]
+    snap: snap::SnapDivision,
+    #[serde(default)]
+    overlap_policy: overlap::OverlapPolicy,
+}
+
+fn default_backup_count() -> usize {
+    backup::DEFAULT_BACKUP_COUNT
 }
 
 //TODO: ehhhhhhhhh
@@ -343,6 +420,21 @@ impl Modifiers {
     }
 }
 
+impl Config {
+    /// Adds the default binding of every event that has no key in the loaded bindings, so
+    /// hotkeys added since the config was stored show up. Keys the user already uses are
+    /// left as they are.
+    fn add_missing_bindings(&mut self) {
+        for (key, event) in Config::default().key_bindings {
+            if !self.key_bindings.contains_key(&key)
+                && !self.key_bindings.values().any(|bound| *bound == event)
+            {
+                self.key_bindings.insert(key, event);
+            }
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         let mut default_bindings = HashMap::new();
@@ -384,6 +476,25 @@ impl Default for Config {
             KeyCombo::new(Key::Y, Modifiers::new().ctrl()),
             GuiEvent::Redo,
         );
+        default_bindings.insert(
+            KeyCombo::new(Key::H, Modifiers::new().ctrl()),
+            GuiEvent::History,
+        );
+        default_bindings.insert(
+            KeyCombo::new(Key::A, Modifiers::new().ctrl()),
+            GuiEvent::SelectAll,
+        );
+        default_bindings.insert(KeyCombo::new(Key::Escape, nomod), GuiEvent::ClearSelection);
+        default_bindings.insert(KeyCombo::new(Key::G, nomod), GuiEvent::SnapFiner);
+        default_bindings.insert(KeyCombo::new(Key::ArrowUp, nomod), GuiEvent::NudgeUp);
+        default_bindings.insert(KeyCombo::new(Key::ArrowDown, nomod), GuiEvent::NudgeDown);
+        default_bindings.insert(KeyCombo::new(Key::ArrowLeft, nomod), GuiEvent::NudgeLeft);
+        default_bindings.insert(KeyCombo::new(Key::ArrowRight, nomod), GuiEvent::NudgeRight);
+        default_bindings.insert(
+            KeyCombo::new(Key::G, Modifiers::new().shift()),
+            GuiEvent::SnapCoarser,
+        );
+        default_bindings.insert(KeyCombo::new(Key::W, nomod), GuiEvent::ToggleLaserWide);
 
         //Tools
         {
@@ -419,6 +530,14 @@ impl Default for Config {
                 KeyCombo::new(Key::Num7, nomod),
                 GuiEvent::ToolChanged(ChartTool::Camera),
             );
+            default_bindings.insert(
+                KeyCombo::new(Key::Num8, nomod),
+                GuiEvent::ToolChanged(ChartTool::Select),
+            );
+            default_bindings.insert(
+                KeyCombo::new(Key::Num9, nomod),
+                GuiEvent::ToolChanged(ChartTool::Eraser),
+            );
         }
 
         default_bindings.insert(KeyCombo::new(Key::Space, nomod), GuiEvent::Play);
@@ -432,6 +551,9 @@ impl Default for Config {
             track_width: 72.0,
             beats_per_column: 16,
             language: "en".parse().unwrap(),
+            backup_count: backup::DEFAULT_BACKUP_COUNT,
+            snap: Default::default(),
+            overlap_policy: Default::default(),
         }
     }
 }
@@ -451,13 +573,15 @@ pub fn rect_xy_wh(rect: [f32; 4]) -> Rect {
     Rect::from_x_y_ranges(x..=x + w, y..=y + h)
 }
 
-const TOOLS: [(&str, ChartTool); 6] = [
+const TOOLS: [(&str, ChartTool); 8] = [
     ("BT", ChartTool::BT),
     ("FX", ChartTool::FX),
     ("LL", ChartTool::LLaser),
     ("RL", ChartTool::RLaser),
     ("BPM", ChartTool::BPM),
     ("TS", ChartTool::TimeSig),
+    ("SEL", ChartTool::Select),
+    ("ERA", ChartTool::Eraser),
 ];
 
 impl AppState {
@@ -476,6 +600,24 @@ impl AppState {
                 .text(i18n::fl!("beats_per_col")),
         );
 
+        ui.add(
+            Slider::new(&mut self.editor.backup_count, 0..=20)
+                .clamp_to_range(true)
+                .text(i18n::fl!("backup_count")),
+        );
+
+        ComboBox::new("overlap_policy", i18n::fl!("overlap_policy"))
+            .selected_text(self.editor.overlap_policy.to_string())
+            .show_ui(ui, |ui| {
+                for policy in overlap::OverlapPolicy::ALL {
+                    ui.selectable_value(
+                        &mut self.editor.overlap_policy,
+                        policy,
+                        policy.to_string(),
+                    );
+                }
+            });
+
         let selected = ComboBox::new("lang_select", "Language")
             .selected_text(&self.language.language.to_string())
             .show_ui(ui, |ui| {
@@ -517,6 +659,170 @@ impl AppState {
     }
 }
 
+impl AppState {
+    fn backup_menu(&mut self, ui: &mut Ui, chart_path: &Path) {
+        let backups = match backup::list(chart_path) {
+            Ok(backups) => backups,
+            Err(e) => {
+                ui.label(format!("{:#}", e));
+                return;
+            }
+        };
+
+        if backups.is_empty() {
+            ui.add_enabled(false, Label::new(i18n::fl!("no_backups")));
+        }
+
+        for backup in backups {
+            if ui.button(backup.label()).clicked() {
+                self.editor
+                    .gui_event_queue
+                    .push_back(GuiEvent::RestoreBackup(backup.path));
+                ui.close_menu();
+            }
+        }
+    }
+
+    fn chart_context_menu(&mut self, ui: &mut Ui) {
+        self.editor.context_menu(ui);
+        if ui
+            .add_enabled(
+                !self.editor.selection.is_empty(),
+                Button::new(i18n::fl!("edit_properties")),
+            )
+            .clicked()
+        {
+            self.show_inspector = true;
+            ui.close_menu();
+        }
+
+        let mut borrowed_tool = self.editor.cursor_object.take();
+        if let Some(tool) = borrowed_tool.as_mut() {
+            tool.context_menu(&mut self.editor, ui);
+        }
+        self.editor.cursor_object = borrowed_tool;
+    }
+
+    fn measures_menu(&mut self, ui: &mut Ui) {
+        ui.add(DragValue::new(&mut self.measure_count).clamp_range(1..=999));
+        let count = self.measure_count;
+        if ui.button(i18n::fl!("insert_measures_at_cursor")).clicked() {
+            self.editor
+                .gui_event_queue
+                .push_back(GuiEvent::InsertMeasures(count));
+            ui.close_menu();
+        }
+        if ui.button(i18n::fl!("delete_measures_at_cursor")).clicked() {
+            self.editor
+                .gui_event_queue
+                .push_back(GuiEvent::DeleteMeasures(count));
+            ui.close_menu();
+        }
+    }
+
+    fn transform_menu(&mut self, ui: &mut Ui) {
+        let has_selection = !self.editor.selection.is_empty();
+        let options = &mut self.transform_options;
+        options.selection_only &= has_selection;
+        ui.add_enabled(
+            has_selection,
+            egui::Checkbox::new(
+                &mut options.selection_only,
+                i18n::fl!("transform_selected_range"),
+            ),
+        );
+        ui.separator();
+
+        let mut transform = None;
+        if ui.button(i18n::fl!("mirror")).clicked() {
+            transform = Some(transform::Transform::Mirror);
+        }
+        ui.horizontal(|ui| {
+            ui.add(DragValue::new(&mut options.shift).suffix(" ticks"));
+            if ui.button(i18n::fl!("shift")).clicked() {
+                transform = Some(transform::Transform::Shift(options.shift));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(DragValue::new(&mut options.scale.0).clamp_range(1..=u32::MAX));
+            ui.label("/");
+            ui.add(DragValue::new(&mut options.scale.1).clamp_range(1..=u32::MAX));
+            if ui.button(i18n::fl!("scale")).clicked() {
+                transform = Some(transform::Transform::Scale {
+                    numerator: options.scale.0,
+                    denominator: options.scale.1,
+                });
+            }
+        });
+
+        if let Some(transform) = transform {
+            self.editor.gui_event_queue.push_back(GuiEvent::Transform {
+                transform,
+                selection_only: options.selection_only,
+            });
+            ui.close_menu();
+        }
+    }
+
+    fn history(&mut self, ui: &mut Ui) {
+        let actions = &self.editor.actions;
+        let saved = actions.saved_id();
+        let current = actions.head();
+        let applied = actions.applied();
+        let mut jump = None;
+
+        let mut entry = |ui: &mut Ui, id: Option<u32>, description: &str, indent: usize| {
+            let mut text = RichText::new(description);
+            if matches!(id, Some(id) if !applied.contains(&id)) {
+                text = text.weak();
+            }
+            if id == saved {
+                text = text.strong();
+            }
+
+            ui.horizontal(|ui| {
+                ui.add_space(indent as f32 * 12.0);
+                let response = ui.selectable_label(id == current, text);
+                let response = if id == saved {
+                    response.on_hover_text(i18n::fl!("saved_state"))
+                } else {
+                    response
+                };
+
+                if response.clicked() {
+                    jump = Some(id);
+                }
+            });
+        };
+
+        // Abandoned branches are listed indented right below where they split off,
+        // the branch that redo follows continues at the same indentation.
+        let mut stack = vec![(None, 0)];
+        while let Some((id, indent)) = stack.pop() {
+            match id {
+                None => entry(ui, None, &i18n::fl!("initial_state"), indent),
+                Some(id) => {
+                    if let Some(action) = actions.get(id) {
+                        entry(ui, Some(id), &action.description, indent)
+                    }
+                }
+            }
+
+            let next = actions.next_in_branch(id);
+            stack.extend(next.map(|next| (Some(next), indent)));
+            for &child in actions.children(id).iter().rev() {
+                if Some(child) != next {
+                    stack.push((Some(child), indent + 1));
+                }
+            }
+        }
+
+        if let Some(id) = jump {
+            self.editor.actions.jump_to(id);
+        }
+    }
+}
+
 const CONFIG_KEY: &str = "CONFIG_2";
 
 fn menu_ui(ui: &mut Ui, title: impl ToString, min_width: f32, add_contents: impl FnOnce(&mut Ui)) {
@@ -548,6 +854,9 @@ impl App for AppState {
             beats_per_column: self.editor.screen.beats_per_col,
             track_width: self.editor.screen.track_width,
             language: self.language.clone(),
+            backup_count: self.editor.backup_count,
+            snap: self.editor.snap,
+            overlap_policy: self.editor.overlap_policy,
         };
 
         eframe::set_value(storage, CONFIG_KEY, &new_config)
@@ -565,8 +874,16 @@ impl App for AppState {
         let events = { ctx.input().events.clone() };
         for e in events {
             match e {
-                egui::Event::Copy => {}
-                egui::Event::Cut => {}
+                // Text fields handle the clipboard themselves while they have focus
+                egui::Event::Copy if !ctx.wants_keyboard_input() => {
+                    self.editor.gui_event_queue.push_back(GuiEvent::Copy)
+                }
+                egui::Event::Cut if !ctx.wants_keyboard_input() => {
+                    self.editor.gui_event_queue.push_back(GuiEvent::Cut)
+                }
+                egui::Event::Paste(text) if !ctx.wants_keyboard_input() => {
+                    self.editor.gui_event_queue.push_back(GuiEvent::Paste(text))
+                }
                 egui::Event::Key {
                     key,
                     pressed,
@@ -585,6 +902,7 @@ impl App for AppState {
                                 }
                             }
                             Some(GuiEvent::Preferences) => self.show_preferences = true,
+                            Some(GuiEvent::History) => self.show_history = !self.show_history,
                             Some(GuiEvent::Metadata) => {
                                 self.meta_edit = Some(self.editor.chart.meta.clone())
                             }
@@ -605,7 +923,7 @@ impl App for AppState {
         }
 
         if let Err(e) = self.editor.update(ctx) {
-            panic!("{}", e);
+            self.editor.notifications.error(&e);
         }
 
         //draw
@@ -629,6 +947,11 @@ impl App for AppState {
                         if ui.button(i18n::fl!("export_ksh")).clicked() {
                             self.editor.gui_event_queue.push_back(GuiEvent::ExportKsh)
                         }
+                        if let Some(path) = self.editor.save_path.clone() {
+                            ui.menu_button(i18n::fl!("restore_backup"), |ui| {
+                                self.backup_menu(ui, &path);
+                            });
+                        }
                         ui.separator();
                         if ui.button(i18n::fl!("preferences")).clicked() {
                             self.show_preferences = true;
@@ -667,6 +990,51 @@ impl App for AppState {
                             self.editor.gui_event_queue.push_back(GuiEvent::Redo);
                         }
 
+                        if ui.button(i18n::fl!("history")).clicked() {
+                            self.show_history = true;
+                        }
+                        ui.checkbox(&mut self.show_inspector, i18n::fl!("inspector"));
+
+                        ui.separator();
+                        if ui.button(i18n::fl!("select_all")).clicked() {
+                            self.editor.gui_event_queue.push_back(GuiEvent::SelectAll);
+                        }
+                        if ui
+                            .add_enabled(
+                                !self.editor.selection.is_empty(),
+                                Button::new(i18n::fl!("clear_selection")),
+                            )
+                            .clicked()
+                        {
+                            self.editor
+                                .gui_event_queue
+                                .push_back(GuiEvent::ClearSelection);
+                        }
+                        ui.menu_button(i18n::fl!("lock_layers"), |ui| {
+                            self.editor.layer_locks_menu(ui);
+                        });
+
+                        if ui
+                            .add_enabled(
+                                !self.editor.selected_laser_sections().is_empty(),
+                                Button::new(i18n::fl!("toggle_laser_wide")),
+                            )
+                            .clicked()
+                        {
+                            self.editor
+                                .gui_event_queue
+                                .push_back(GuiEvent::ToggleLaserWide);
+                        }
+
+                        ui.separator();
+                        ui.menu_button(i18n::fl!("measures"), |ui| {
+                            self.measures_menu(ui);
+                        });
+
+                        if ui.button(i18n::fl!("change_resolution")).clicked() {
+                            self.resolution_edit = Some(self.editor.chart.beat.resolution);
+                        }
+
                         ui.separator();
                         if ui.button(i18n::fl!("metadata")).clicked() && self.meta_edit.is_none() {
                             self.meta_edit = Some(self.editor.chart.meta.clone());
@@ -679,6 +1047,10 @@ impl App for AppState {
                         }
                     });
 
+                    menu_ui(ui, i18n::fl!("transform"), 160.0, |ui| {
+                        self.transform_menu(ui);
+                    });
+
                     if !self.editor.actions.saved() {
                         ui.with_layout(Layout::right_to_left(), |ui| {
                             ui.add(egui::Label::new(RichText::new("*").color(Color32::RED)))
@@ -704,6 +1076,19 @@ impl App for AppState {
                             }
                         }
                     }
+
+                    ui.separator();
+                    ComboBox::from_label(i18n::fl!("snap"))
+                        .selected_text(self.editor.snap.to_string())
+                        .show_ui(ui, |ui| {
+                            for division in snap::SnapDivision::all() {
+                                ui.selectable_value(
+                                    &mut self.editor.snap,
+                                    division,
+                                    division.to_string(),
+                                );
+                            }
+                        });
                 })
             });
         }
@@ -720,6 +1105,21 @@ impl App for AppState {
                 });
             self.show_preferences = open;
 
+            //History window
+            let mut open = self.show_history;
+            egui::Window::new(i18n::fl!("history"))
+                .open(&mut open)
+                .resizable(true)
+                .default_width(220.0)
+                .show(ctx, |ui| {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        ui.with_layout(Layout::top_down_justified(egui::Align::Min), |ui| {
+                            self.history(ui);
+                        });
+                    });
+                });
+            self.show_history = open;
+
             //New chart dialog
             if let Some(new_chart) = &mut self.new_chart {
                 let mut open = true;
@@ -742,49 +1142,161 @@ impl App for AppState {
                 }
             }
 
-            //Metadata dialog
+            //Metadata dialog, changes are applied as they are made and merged into one action
             if self.meta_edit.is_some() {
                 let mut open = true;
+                let mut closed = false;
                 egui::Window::new(i18n::fl!("metadata"))
                     .open(&mut open)
                     .show(ctx, |ui| {
-                        self.meta_edit.as_mut().unwrap().ui(ui);
+                        let meta = self.meta_edit.as_mut().unwrap();
+                        if meta.ui(ui).changed() {
+                            self.editor.actions.new_merging_action(
+                                "metadata",
+                                i18n::fl!("update_metadata"),
+                                ChartEdit::SetMeta(meta.clone()),
+                            );
+                        }
                         ui.add_space(10.0);
                         if ui.button(i18n::fl!("ok")).clicked() {
-                            let new_action = self.editor.actions.new_action();
-                            let new_meta = self.meta_edit.take().unwrap();
-                            new_action.action = Box::new(move |chart: &mut Chart| {
-                                chart.meta = new_meta.clone();
-                                Ok(())
-                            });
-                            new_action.description = String::from(i18n::fl!("update_metadata"));
+                            closed = true;
                         }
                     });
-                if !open {
+                if !open || closed {
                     self.meta_edit = None;
+                    self.editor.actions.seal();
+                }
+            }
+
+            //Resolution dialog, lists what would be rounded before anything is changed
+            if let Some(resolution) = &mut self.resolution_edit {
+                let mut open = true;
+                let mut closed = false;
+                let editor = &mut self.editor;
+                let cached_report = &mut self.resolution_report;
+                egui::Window::new(i18n::fl!("change_resolution"))
+                    .open(&mut open)
+                    .resizable(false)
+                    .show(ctx, |ui| {
+                        ui.label(i18n::fl!(
+                            "current_resolution",
+                            resolution = editor.chart.beat.resolution
+                        ));
+                        ui.horizontal(|ui| {
+                            ui.label(i18n::fl!("new_resolution"));
+                            ui.add(DragValue::new(resolution).clamp_range(1..=9600));
+                        });
+                        ui.separator();
+
+                        let revision = editor.chart_revision;
+                        let up_to_date = matches!(
+                            cached_report,
+                            Some((r, res, _)) if *r == revision && *res == *resolution
+                        );
+                        if !up_to_date {
+                            *cached_report = None;
+                        }
+                        let (_, _, report) = cached_report.get_or_insert_with(|| {
+                            (
+                                revision,
+                                *resolution,
+                                transform::resolution_report(&editor.chart, *resolution),
+                            )
+                        });
+                        let impossible = report.has_inexact_time_signatures();
+                        if report.is_empty() {
+                            ui.label(i18n::fl!("resolution_exact"));
+                        } else {
+                            if impossible {
+                                ui.colored_label(
+                                    Color32::RED,
+                                    i18n::fl!("resolution_time_signatures"),
+                                );
+                            }
+                            ui.label(i18n::fl!(
+                                "resolution_inexact",
+                                count = report.objects.len() + report.camera_points
+                            ));
+                            if ui.button(i18n::fl!("select_inexact")).clicked() {
+                                editor.selection.clear();
+                                editor.selection.extend(report.objects.iter().copied());
+                            }
+                        }
+                        ui.add_space(10.0);
+
+                        ui.horizontal(|ui| {
+                            let apply_text = if report.is_empty() {
+                                i18n::fl!("ok")
+                            } else {
+                                i18n::fl!("apply_rounded")
+                            };
+                            if ui
+                                .add_enabled(!impossible, Button::new(apply_text))
+                                .clicked()
+                            {
+                                if *resolution != editor.chart.beat.resolution {
+                                    editor.change_resolution(*resolution);
+                                }
+                                closed = true;
+                            }
+                            if ui.button(i18n::fl!("cancel")).clicked() {
+                                closed = true;
+                            }
+                        });
+                    });
+                if !open || closed {
+                    self.resolution_edit = None;
+                    self.resolution_report = None;
                 }
             }
 
             //Music data dialog
             if self.bgm_edit.is_some() {
                 let mut open = true;
+                let mut closed = false;
                 egui::Window::new(i18n::fl!("music_info"))
                     .open(&mut open)
                     .show(ctx, |ui| {
-                        self.bgm_edit.as_mut().unwrap().ui(ui);
+                        let bgm = self.bgm_edit.as_mut().unwrap();
+                        if bgm.ui(ui).changed() {
+                            self.editor.actions.new_merging_action(
+                                "music_info",
+                                i18n::fl!("update_music_info"),
+                                ChartEdit::SetBgm(bgm.clone()),
+                            );
+                        }
                         ui.add_space(10.0);
                         if ui.button(i18n::fl!("ok")).clicked() {
-                            let new_action = self.editor.actions.new_action();
-                            let new_bgm = self.bgm_edit.take().unwrap();
-                            new_action.description = i18n::fl!("update_music_info").into();
-                            new_action.action = Box::new(move |chart: &mut Chart| {
-                                chart.audio.bgm = Some(new_bgm.clone());
-                                Ok(())
-                            });
+                            closed = true;
                         }
                     });
-                if !open {
+                if !open || closed {
                     self.bgm_edit = None;
+                    self.editor.actions.seal();
+                }
+            }
+
+            //Recovery dialog
+            if self.editor.pending_recovery.is_some() {
+                let mut result = None;
+                egui::Window::new(i18n::fl!("recover_unsaved_changes"))
+                    .collapsible(false)
+                    .resizable(false)
+                    .show(ctx, |ui| {
+                        ui.label(i18n::fl!("recovery_file_found"));
+                        ui.horizontal(|ui| {
+                            if ui.button(i18n::fl!("restore")).clicked() {
+                                result = Some(self.editor.restore_recovery());
+                            }
+                            if ui.button(i18n::fl!("discard")).clicked() {
+                                result = Some(self.editor.discard_recovery());
+                            }
+                        });
+                    });
+
+                if let Some(Err(e)) = result {
+                    self.editor.pending_recovery = None;
+                    self.editor.notifications.error(&e);
                 }
             }
         }
@@ -807,6 +1319,17 @@ impl App for AppState {
                 self.editor.cursor_object = borrowed_tool;
             }
 
+            if self.show_inspector {
+                egui::SidePanel::right("inspector")
+                    .resizable(true)
+                    .default_width(200.0)
+                    .show(ctx, |ui| {
+                        ui.heading(i18n::fl!("inspector"));
+                        ui.separator();
+                        self.inspector.ui(ui, &mut self.editor);
+                    });
+            }
+
             let main_response = egui::CentralPanel::default()
                 .frame(main_frame)
                 .show(ctx, |ui| self.editor.draw(ui))
@@ -815,6 +1338,9 @@ impl App for AppState {
             match main_response {
                 Ok(response) => {
                     let pos = ctx.input().pointer.hover_pos().unwrap_or(Pos2::ZERO);
+                    if response.hovered() {
+                        self.inspector.hover(&self.editor);
+                    }
                     if response.hovered() && ctx.input().scroll_delta != Vec2::ZERO {
                         self.editor.mouse_wheel_event(ctx.input().scroll_delta.y);
                     }
@@ -827,6 +1353,10 @@ impl App for AppState {
                         self.editor.middle_clicked(pos)
                     }
 
+                    if response.secondary_clicked() {
+                        self.editor.secondary_clicked(pos)
+                    }
+
                     if response.drag_started()
                         && ctx
                             .input()
@@ -845,8 +1375,10 @@ impl App for AppState {
                         self.editor
                             .drag_end(egui::PointerButton::Primary, pos.x, pos.y)
                     }
+
+                    response.context_menu(|ui| self.chart_context_menu(ui));
                 }
-                Err(e) => panic!("{}", e),
+                Err(e) => self.editor.notifications.error(&e),
             }
         }
         //exiting
@@ -859,13 +1391,21 @@ impl App for AppState {
                         ui.horizontal(|ui| {
                             if ui.button(i18n::fl!("yes")).clicked() {
                                 self.exiting = false;
-                                if matches!(self.editor.save(), Ok(true)) {
-                                    frame.quit();
+                                match self.editor.save() {
+                                    Ok(true) => frame.quit(),
+                                    Ok(false) => (),
+                                    Err(e) => self
+                                        .editor
+                                        .notifications
+                                        .error(&e.context(i18n::fl!("failed_to_save"))),
                                 }
                             }
                             if ui.button(i18n::fl!("no")).clicked() {
                                 self.exiting = false;
                                 self.editor.actions.save(); //marks as saved but doesn't actually save
+                                if let Err(e) = self.editor.discard_recovery() {
+                                    log::warn!("Could not remove recovery file: {:#}", e);
+                                }
                                 frame.quit();
                             }
                             if ui.button(i18n::fl!("cancel")).clicked() {
@@ -875,6 +1415,8 @@ impl App for AppState {
                     });
             }
         }
+
+        self.editor.notifications.show(ctx);
     }
 }
 
@@ -896,19 +1438,27 @@ fn main() -> Result<()> {
         "KSON Editor",
         options,
         Box::new(|cc| {
-            let config = if let Some(storage) = cc.storage {
+            let mut config = if let Some(storage) = cc.storage {
                 let c: Option<Config> = eframe::get_value(storage, CONFIG_KEY);
                 c.unwrap_or_default()
             } else {
                 Config::default()
             };
+            config.add_missing_bindings();
 
             let mut app = AppState {
                 editor: MainState::new().unwrap_or_else(|_| todo!()),
                 key_bindings: HashMap::new(),
                 show_preferences: false,
+                show_history: false,
+                show_inspector: true,
+                inspector: Default::default(),
+                transform_options: Default::default(),
+                measure_count: 1,
                 new_chart: None,
                 meta_edit: None,
+                resolution_edit: None,
+                resolution_report: None,
                 bgm_edit: None,
                 exiting: false,
                 language: config.language,
@@ -917,6 +1467,9 @@ fn main() -> Result<()> {
             app.key_bindings = config.key_bindings;
             app.editor.screen.track_width = config.track_width;
             app.editor.screen.beats_per_col = config.beats_per_column;
+            app.editor.backup_count = config.backup_count;
+            app.editor.snap = config.snap;
+            app.editor.overlap_policy = config.overlap_policy;
             cc.egui_ctx.set_visuals(Visuals::dark());
 
             Box::new(app)
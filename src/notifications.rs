@@ -0,0 +1,94 @@
+use std::time::{Duration, Instant};
+
+use eframe::egui::{self, Align2, Color32, Context, Frame, RichText};
+
+const INFO_DURATION: Duration = Duration::from_secs(4);
+const ERROR_DURATION: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Severity {
+    Info,
+    Error,
+}
+
+pub struct Notification {
+    pub message: String,
+    pub severity: Severity,
+    created: Instant,
+}
+
+/// Short messages shown in the corner of the editor that disappear after a while.
+#[derive(Default)]
+pub struct Notifications {
+    entries: Vec<Notification>,
+}
+
+impl Notifications {
+    /// Adds a notification, a message identical to one already shown restarts its timer instead.
+    pub fn push(&mut self, severity: Severity, message: impl Into<String>) {
+        let message = message.into();
+        if let Some(existing) = self.entries.iter_mut().find(|n| n.message == message) {
+            existing.created = Instant::now();
+            return;
+        }
+
+        self.entries.push(Notification {
+            message,
+            severity,
+            created: Instant::now(),
+        });
+    }
+
+    pub fn info(&mut self, message: impl Into<String>) {
+        self.push(Severity::Info, message)
+    }
+
+    /// Reports an error along with its chain of causes.
+    pub fn error(&mut self, error: &anyhow::Error) {
+        log::error!("{:#}", error);
+        self.push(Severity::Error, format!("{:#}", error))
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        self.entries.retain(|n| {
+            let duration = match n.severity {
+                Severity::Info => INFO_DURATION,
+                Severity::Error => ERROR_DURATION,
+            };
+            n.created.elapsed() < duration
+        });
+
+        if self.entries.is_empty() {
+            return;
+        }
+
+        let mut dismissed = None;
+        egui::Area::new("notifications")
+            .anchor(Align2::RIGHT_BOTTOM, [-10.0, -10.0])
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                for (i, notification) in self.entries.iter().enumerate() {
+                    Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_max_width(320.0);
+                        ui.horizontal(|ui| {
+                            let text = RichText::new(&notification.message);
+                            match notification.severity {
+                                Severity::Info => ui.label(text),
+                                Severity::Error => ui.label(text.color(Color32::LIGHT_RED)),
+                            };
+                            if ui.small_button("x").clicked() {
+                                dismissed = Some(i);
+                            }
+                        });
+                    });
+                }
+            });
+
+        if let Some(i) = dismissed {
+            self.entries.remove(i);
+        }
+
+        // Keep repainting so notifications expire without any input
+        ctx.request_repaint();
+    }
+}
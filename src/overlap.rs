@@ -0,0 +1,373 @@
+use kson::{Chart, Interval, LaserSection};
+use serde::{Deserialize, Serialize};
+
+use crate::chart_edit::ChartEdit;
+use crate::i18n;
+use crate::utils::Overlaps;
+
+/// What to do when a new note or laser section overlaps existing ones in the same lane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OverlapPolicy {
+    /// Don't place it.
+    Reject,
+    /// Shorten it so it ends before the next object.
+    Trim,
+    /// Combine it with the objects it overlaps.
+    Merge,
+}
+
+impl Default for OverlapPolicy {
+    fn default() -> Self {
+        OverlapPolicy::Reject
+    }
+}
+
+impl ToString for OverlapPolicy {
+    fn to_string(&self) -> String {
+        match self {
+            OverlapPolicy::Reject => i18n::fl!("overlap_reject"),
+            OverlapPolicy::Trim => i18n::fl!("overlap_trim"),
+            OverlapPolicy::Merge => i18n::fl!("overlap_merge"),
+        }
+    }
+}
+
+impl OverlapPolicy {
+    pub const ALL: [OverlapPolicy; 3] = [
+        OverlapPolicy::Reject,
+        OverlapPolicy::Trim,
+        OverlapPolicy::Merge,
+    ];
+}
+
+/// The edit that places `interval` in a BT or FX lane following `policy`, `None` if it
+/// can't be placed.
+pub fn place_interval(
+    chart: &Chart,
+    fx: bool,
+    lane: usize,
+    interval: Interval,
+    policy: OverlapPolicy,
+) -> Option<ChartEdit> {
+    let notes = if fx {
+        chart.note.fx.get(lane)?
+    } else {
+        chart.note.bt.get(lane)?
+    };
+    let overlapping: Vec<&Interval> = notes.iter().filter(|n| n.overlaps(&interval)).collect();
+
+    if overlapping.is_empty() {
+        return Some(ChartEdit::AddInterval { fx, lane, interval });
+    }
+
+    match policy {
+        OverlapPolicy::Reject => None,
+        OverlapPolicy::Trim => {
+            if overlapping.iter().any(|n| n.contains(interval.y)) {
+                return None;
+            }
+            // Everything left overlaps the end of the new note
+            let next = overlapping.iter().map(|n| n.y).min()?;
+            let interval = Interval {
+                y: interval.y,
+                l: next - 1 - interval.y,
+            };
+            Some(ChartEdit::AddInterval { fx, lane, interval })
+        }
+        OverlapPolicy::Merge => {
+            let start = overlapping.iter().map(|n| n.y).fold(interval.y, u32::min);
+            let end = overlapping
+                .iter()
+                .map(|n| n.y + n.l)
+                .fold(interval.y + interval.l, u32::max);
+
+            let mut edits: Vec<ChartEdit> = overlapping
+                .iter()
+                .map(|n| ChartEdit::RemoveInterval { fx, lane, y: n.y })
+                .collect();
+            edits.push(ChartEdit::AddInterval {
+                fx,
+                lane,
+                interval: Interval {
+                    y: start,
+                    l: end - start,
+                },
+            });
+            Some(ChartEdit::Batch(edits))
+        }
+    }
+}
+
+/// The edit that places a laser section on `side` following `policy`, `None` if it can't
+/// be placed.
+///
+/// Merging keeps the points of the overlapped sections that are outside of the new
+/// section and joins everything into one section.
+pub fn place_laser_section(
+    chart: &Chart,
+    side: usize,
+    section: LaserSection,
+    policy: OverlapPolicy,
+) -> Option<ChartEdit> {
+    let sections = chart.note.laser.get(side)?;
+    let overlapping: Vec<&LaserSection> =
+        sections.iter().filter(|s| s.overlaps(&section)).collect();
+
+    if overlapping.is_empty() {
+        return Some(ChartEdit::AddLaserSection { side, section });
+    }
+
+    match policy {
+        OverlapPolicy::Reject => None,
+        OverlapPolicy::Trim => {
+            if overlapping.iter().any(|s| s.contains(section.tick())) {
+                return None;
+            }
+            let next = overlapping.iter().map(|s| s.tick()).min()?;
+            let mut section = section;
+            let start = section.tick();
+            section.1.retain(|p| start + p.ry < next);
+            if section.1.len() < 2 {
+                return None;
+            }
+            Some(ChartEdit::AddLaserSection { side, section })
+        }
+        OverlapPolicy::Merge => {
+            let start = section.tick();
+            let end = start + section.last()?.ry;
+
+            let mut points: Vec<_> = section.1.iter().map(|p| (start + p.ry, *p)).collect();
+            for s in &overlapping {
+                points.extend(
+                    s.1.iter()
+                        .map(|p| (s.tick() + p.ry, *p))
+                        .filter(|(tick, _)| *tick < start || *tick > end),
+                );
+            }
+            points.sort_by_key(|(tick, _)| *tick);
+
+            let merged_start = points.first()?.0;
+            let mut merged = section;
+            merged.0 = merged_start;
+            merged.1 = points
+                .into_iter()
+                .map(|(tick, mut p)| {
+                    p.ry = tick - merged_start;
+                    p
+                })
+                .collect();
+
+            let mut edits: Vec<ChartEdit> = overlapping
+                .iter()
+                .map(|s| ChartEdit::RemoveLaserSection {
+                    side,
+                    tick: s.tick(),
+                })
+                .collect();
+            edits.push(ChartEdit::AddLaserSection {
+                side,
+                section: merged,
+            });
+            Some(ChartEdit::Batch(edits))
+        }
+    }
+}
+
+/// Whether `interval` fits in a BT or FX lane without overlapping any note other than the
+/// one at `replacing`.
+pub fn interval_fits(
+    chart: &Chart,
+    fx: bool,
+    lane: usize,
+    interval: &Interval,
+    replacing: Option<u32>,
+) -> bool {
+    let notes = if fx {
+        chart.note.fx.get(lane)
+    } else {
+        chart.note.bt.get(lane)
+    };
+    notes.map_or(false, |notes| {
+        notes
+            .iter()
+            .filter(|n| Some(n.y) != replacing)
+            .all(|n| !n.overlaps(interval))
+    })
+}
+
+/// Whether `section` fits on `side` without overlapping any section other than the one
+/// starting at `replacing`.
+pub fn laser_section_fits(
+    chart: &Chart,
+    side: usize,
+    section: &LaserSection,
+    replacing: Option<u32>,
+) -> bool {
+    chart.note.laser.get(side).map_or(false, |sections| {
+        sections
+            .iter()
+            .filter(|s| Some(s.tick()) != replacing)
+            .all(|s| !s.overlaps(section))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action_stack::Document;
+    use crate::chart_edit::tests::{chart, notes, section, sections};
+
+    fn chart_with_notes(existing: &[(u32, u32)]) -> Chart {
+        let mut chart = chart();
+        chart.note.bt[0] = existing.iter().map(|&(y, l)| Interval { y, l }).collect();
+        chart
+    }
+
+    /// Places the note and returns the notes in the lane, `None` if it wasn't placed.
+    fn place(
+        existing: &[(u32, u32)],
+        y: u32,
+        l: u32,
+        policy: OverlapPolicy,
+    ) -> Option<Vec<(u32, u32)>> {
+        let mut chart = chart_with_notes(existing);
+        let edit = place_interval(&chart, false, 0, Interval { y, l }, policy)?;
+        chart.apply(&edit).unwrap();
+        Some(notes(&chart, false, 0))
+    }
+
+    fn place_section(
+        existing: LaserSection,
+        new: LaserSection,
+        policy: OverlapPolicy,
+    ) -> Option<Vec<(u32, Vec<(u32, f64)>)>> {
+        let mut chart = chart();
+        chart.note.laser[0].push(existing);
+        let edit = place_laser_section(&chart, 0, new, policy)?;
+        chart.apply(&edit).unwrap();
+        Some(sections(&chart, 0))
+    }
+
+    #[test]
+    fn place_interval_without_overlap() {
+        for &policy in OverlapPolicy::ALL.iter() {
+            assert_eq!(
+                place(&[(240, 240)], 0, 120, policy),
+                Some(vec![(0, 120), (240, 240)])
+            );
+        }
+    }
+
+    #[test]
+    fn reject_overlapping_interval() {
+        assert_eq!(place(&[(240, 240)], 360, 0, OverlapPolicy::Reject), None);
+        assert_eq!(place(&[(240, 240)], 0, 240, OverlapPolicy::Reject), None);
+    }
+
+    #[test]
+    fn trim_overlapping_interval() {
+        assert_eq!(
+            place(&[(240, 240)], 0, 480, OverlapPolicy::Trim),
+            Some(vec![(0, 239), (240, 240)])
+        );
+        assert_eq!(
+            place(&[(240, 240), (600, 0)], 0, 960, OverlapPolicy::Trim),
+            Some(vec![(0, 239), (240, 240), (600, 0)])
+        );
+        assert_eq!(place(&[(240, 240)], 300, 480, OverlapPolicy::Trim), None);
+        assert_eq!(place(&[(240, 240)], 480, 0, OverlapPolicy::Trim), None);
+    }
+
+    #[test]
+    fn merge_overlapping_intervals() {
+        assert_eq!(
+            place(
+                &[(0, 100), (200, 100), (600, 0)],
+                50,
+                200,
+                OverlapPolicy::Merge
+            ),
+            Some(vec![(0, 300), (600, 0)])
+        );
+        assert_eq!(
+            place(&[(240, 240)], 300, 0, OverlapPolicy::Merge),
+            Some(vec![(240, 240)])
+        );
+    }
+
+    #[test]
+    fn reject_overlapping_laser_section() {
+        let existing = section(480, &[(0, 0.0), (240, 1.0)]);
+        let new = section(0, &[(0, 0.0), (480, 1.0)]);
+
+        assert_eq!(place_section(existing, new, OverlapPolicy::Reject), None);
+    }
+
+    #[test]
+    fn trim_overlapping_laser_section() {
+        let existing = section(480, &[(0, 0.0), (240, 1.0)]);
+
+        assert_eq!(
+            place_section(
+                existing.clone(),
+                section(0, &[(0, 0.0), (240, 0.5), (480, 1.0)]),
+                OverlapPolicy::Trim
+            ),
+            Some(vec![
+                (0, vec![(0, 0.0), (240, 0.5)]),
+                (480, vec![(0, 0.0), (240, 1.0)])
+            ])
+        );
+        // Only one point would be left
+        assert_eq!(
+            place_section(
+                existing.clone(),
+                section(240, &[(0, 0.0), (240, 1.0)]),
+                OverlapPolicy::Trim
+            ),
+            None
+        );
+        assert_eq!(
+            place_section(
+                existing,
+                section(600, &[(0, 0.0), (240, 1.0)]),
+                OverlapPolicy::Trim
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn merge_overlapping_laser_sections() {
+        let existing = section(480, &[(0, 0.0), (240, 1.0)]);
+
+        assert_eq!(
+            place_section(
+                existing,
+                section(0, &[(0, 0.5), (600, 0.5)]),
+                OverlapPolicy::Merge
+            ),
+            Some(vec![(0, vec![(0, 0.5), (600, 0.5), (720, 1.0)])])
+        );
+    }
+
+    #[test]
+    fn fits() {
+        let chart = chart_with_notes(&[(100, 0)]);
+        let interval = Interval { y: 0, l: 100 };
+
+        assert!(!interval_fits(&chart, false, 0, &interval, None));
+        assert!(interval_fits(&chart, false, 0, &interval, Some(100)));
+        assert!(interval_fits(&chart, true, 0, &interval, None));
+        assert!(!interval_fits(&chart, false, 4, &interval, None));
+
+        let mut chart = chart;
+        chart.note.laser[1].push(section(240, &[(0, 0.0), (240, 1.0)]));
+        let new = section(0, &[(0, 0.0), (240, 1.0)]);
+
+        assert!(!laser_section_fits(&chart, 1, &new, None));
+        assert!(laser_section_fits(&chart, 1, &new, Some(240)));
+        assert!(laser_section_fits(&chart, 0, &new, None));
+        assert!(!laser_section_fits(&chart, 2, &new, None));
+    }
+}
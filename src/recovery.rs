@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use directories_next::ProjectDirs;
+
+use crate::utils;
+
+/// Time between writes of the recovery file while there are unsaved changes.
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Location of the recovery file for the chart at `chart_path`. Untitled charts are
+/// autosaved to the data directory instead.
+pub fn recovery_path(chart_path: Option<&Path>) -> Result<PathBuf> {
+    match chart_path {
+        Some(path) => {
+            let mut file_name = path
+                .file_name()
+                .ok_or_else(|| anyhow!("Invalid chart path: {}", path.display()))?
+                .to_os_string();
+            file_name.push(".recovery");
+            Ok(path.with_file_name(file_name))
+        }
+        None => ProjectDirs::from("", "", "kson-editor")
+            .map(|dirs| dirs.data_dir().join("untitled.kson.recovery"))
+            .ok_or_else(|| anyhow!("Could not find a data directory")),
+    }
+}
+
+pub fn write(path: &Path, chart: &kson::Chart) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    utils::write_atomic(path, &serde_json::to_vec(chart)?)
+}
+
+/// Returns the recovery file for `chart_path` if there is one that is newer than the chart.
+pub fn find(chart_path: Option<&Path>) -> Option<PathBuf> {
+    let path = recovery_path(chart_path).ok()?;
+    let recovery_modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+    let chart_modified = chart_path.and_then(|p| fs::metadata(p).and_then(|m| m.modified()).ok());
+
+    match chart_modified {
+        Some(chart_modified) if chart_modified >= recovery_modified => None,
+        _ => Some(path),
+    }
+}
+
+pub fn remove(chart_path: Option<&Path>) -> Result<()> {
+    let path = recovery_path(chart_path)?;
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
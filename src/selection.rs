@@ -0,0 +1,465 @@
+use std::collections::BTreeSet;
+
+use eframe::egui::{pos2, Color32, Painter, Stroke};
+use kson::{Chart, Interval, LaserSection};
+use serde::{Deserialize, Serialize};
+
+use crate::chart_editor::{do_curve, MainState};
+use crate::rect_xy_wh;
+
+const HIGHLIGHT_COLOR: Color32 = Color32::from_rgb(80, 200, 255);
+
+/// A chart object identified by its position, so ids stay valid while other objects are edited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ObjectId {
+    Interval {
+        fx: bool,
+        lane: usize,
+        y: u32,
+    },
+    LaserSection {
+        side: usize,
+        tick: u32,
+    },
+    /// The point at `ry` in the laser section starting at `section`.
+    LaserPoint {
+        side: usize,
+        section: u32,
+        ry: u32,
+    },
+    Bpm {
+        tick: u32,
+    },
+    TimeSig {
+        measure: u32,
+    },
+}
+
+impl ObjectId {
+    pub fn exists(&self, chart: &Chart) -> bool {
+        match *self {
+            ObjectId::Interval { fx, lane, y } => {
+                let lanes = if fx {
+                    &chart.note.fx[..]
+                } else {
+                    &chart.note.bt[..]
+                };
+                lanes
+                    .get(lane)
+                    .map_or(false, |l| l.binary_search_by_key(&y, |n| n.y).is_ok())
+            }
+            ObjectId::LaserSection { side, tick } => chart
+                .note
+                .laser
+                .get(side)
+                .map_or(false, |l| l.binary_search_by_key(&tick, |s| s.0).is_ok()),
+            ObjectId::LaserPoint { side, section, ry } => chart
+                .note
+                .laser
+                .get(side)
+                .and_then(|l| l.iter().find(|s| s.0 == section))
+                .map_or(false, |s| s.1.binary_search_by_key(&ry, |p| p.ry).is_ok()),
+            ObjectId::Bpm { tick } => chart.beat.bpm.binary_search_by_key(&tick, |b| b.0).is_ok(),
+            ObjectId::TimeSig { measure } => chart
+                .beat
+                .time_sig
+                .binary_search_by_key(&measure, |t| t.0)
+                .is_ok(),
+        }
+    }
+}
+
+/// Horizontal position of a laser value in lane space, matching how lasers are drawn.
+pub fn laser_lane(v: f64, wide: bool) -> f32 {
+    let v = if wide { v * 2.0 - 0.5 } else { v };
+    v as f32 * 5.0 + 0.5
+}
+
+/// Value of `section` at `ry` along its line as it is drawn, following slams and curves.
+pub fn laser_value_at(section: &LaserSection, ry: u32) -> Option<f64> {
+    let segment = section
+        .segments()
+        .find(|s| s[0].ry <= ry && ry <= s[1].ry)?;
+    let start = segment[0].vf.unwrap_or(segment[0].v);
+    let x = (ry - segment[0].ry) as f64 / (segment[1].ry - segment[0].ry).max(1) as f64;
+    let (a, b) = (segment[0].a.unwrap_or(0.5), segment[0].b.unwrap_or(0.5));
+    let t = if (a - b).abs() < f64::EPSILON {
+        x
+    } else {
+        do_curve(x, a, b)
+    };
+    Some(start + (segment[1].v - start) * t)
+}
+
+/// Layers whose objects can't be selected, moved or erased while they are locked.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LayerLocks {
+    pub bt: bool,
+    pub fx: bool,
+    pub laser: bool,
+    /// BPM and time signature changes
+    pub beat: bool,
+}
+
+impl LayerLocks {
+    pub fn is_locked(&self, object: &ObjectId) -> bool {
+        match object {
+            ObjectId::Interval { fx: false, .. } => self.bt,
+            ObjectId::Interval { fx: true, .. } => self.fx,
+            ObjectId::LaserSection { .. } | ObjectId::LaserPoint { .. } => self.laser,
+            ObjectId::Bpm { .. } | ObjectId::TimeSig { .. } => self.beat,
+        }
+    }
+}
+
+/// An area of the chart in tick and lane space. Lanes go from 0 to 6 across the track like
+/// [`crate::chart_editor::ScreenState::pos_to_lane`], so an area can span several columns
+/// of the view.
+#[derive(Debug, Clone, Copy)]
+pub struct SelectionArea {
+    pub start: u32,
+    pub end: u32,
+    pub lanes: (f32, f32),
+}
+
+impl SelectionArea {
+    /// The area between two corners given as (tick, lane).
+    pub fn new(a: (u32, f32), b: (u32, f32)) -> Self {
+        SelectionArea {
+            start: a.0.min(b.0),
+            end: a.0.max(b.0),
+            lanes: (a.1.min(b.1), a.1.max(b.1)),
+        }
+    }
+
+    /// A small area around a clicked position.
+    pub fn around(tick: u32, lane: f32, resolution: u32) -> Self {
+        let margin = resolution / 8;
+        SelectionArea::new(
+            (tick.saturating_sub(margin), lane - 0.25),
+            (tick + margin, lane + 0.25),
+        )
+    }
+
+    pub fn everything() -> Self {
+        SelectionArea {
+            start: 0,
+            end: u32::MAX,
+            lanes: (0.0, 6.0),
+        }
+    }
+
+    fn contains_tick(&self, tick: u32) -> bool {
+        (self.start..=self.end).contains(&tick)
+    }
+
+    fn contains_lane(&self, lane: f32) -> bool {
+        self.lanes.0 <= lane && lane <= self.lanes.1
+    }
+
+    fn overlaps_lanes(&self, from: f32, to: f32) -> bool {
+        self.lanes.0 <= to && from <= self.lanes.1
+    }
+
+    fn overlaps_interval(&self, interval: &Interval) -> bool {
+        interval.y <= self.end && self.start <= interval.y + interval.l
+    }
+
+    /// Every object inside the area.
+    ///
+    /// Laser sections are included when all of their points are. BPM and time signature
+    /// changes are labeled at the right edge of the track and are only included when the
+    /// area reaches it.
+    pub fn objects(&self, chart: &Chart) -> Vec<ObjectId> {
+        let mut objects = Vec::new();
+
+        for (lane, notes) in chart.note.bt.iter().enumerate() {
+            if self.overlaps_lanes(lane as f32 + 1.0, lane as f32 + 2.0) {
+                objects.extend(notes.iter().filter(|n| self.overlaps_interval(n)).map(|n| {
+                    ObjectId::Interval {
+                        fx: false,
+                        lane,
+                        y: n.y,
+                    }
+                }));
+            }
+        }
+
+        for (lane, notes) in chart.note.fx.iter().enumerate() {
+            if self.overlaps_lanes(lane as f32 * 2.0 + 1.0, lane as f32 * 2.0 + 3.0) {
+                objects.extend(notes.iter().filter(|n| self.overlaps_interval(n)).map(|n| {
+                    ObjectId::Interval {
+                        fx: true,
+                        lane,
+                        y: n.y,
+                    }
+                }));
+            }
+        }
+
+        for (side, sections) in chart.note.laser.iter().enumerate() {
+            for section in sections {
+                let wide = section.wide() == 2;
+                let mut all_points = !section.1.is_empty();
+                for point in &section.1 {
+                    let in_lanes = self.contains_lane(laser_lane(point.v, wide))
+                        || point
+                            .vf
+                            .map_or(false, |vf| self.contains_lane(laser_lane(vf, wide)));
+
+                    if in_lanes && self.contains_tick(section.tick() + point.ry) {
+                        objects.push(ObjectId::LaserPoint {
+                            side,
+                            section: section.tick(),
+                            ry: point.ry,
+                        });
+                    } else {
+                        all_points = false;
+                    }
+                }
+
+                if all_points {
+                    objects.push(ObjectId::LaserSection {
+                        side,
+                        tick: section.tick(),
+                    });
+                }
+            }
+        }
+
+        if self.lanes.1 >= 5.0 {
+            objects.extend(
+                chart
+                    .beat
+                    .bpm
+                    .iter()
+                    .filter(|b| self.contains_tick(b.0))
+                    .map(|b| ObjectId::Bpm { tick: b.0 }),
+            );
+            objects.extend(
+                chart
+                    .beat
+                    .time_sig
+                    .iter()
+                    .filter(|t| self.contains_tick(chart.measure_to_tick(t.0)))
+                    .map(|t| ObjectId::TimeSig { measure: t.0 }),
+            );
+        }
+
+        objects
+    }
+}
+
+/// The unlocked object closest to a clicked position, out of those in
+/// [`SelectionArea::around`] it. Laser sections are left out in favor of their points.
+pub fn nearest(chart: &Chart, tick: u32, lane: f32, locks: &LayerLocks) -> Option<ObjectId> {
+    let margin = (chart.beat.resolution / 8).max(1) as f32;
+    let distance = |object_tick: u32, object_lane: f32| {
+        let ticks = (object_tick as f32 - tick as f32) / margin;
+        let lanes = (object_lane - lane) / 0.25;
+        ticks * ticks + lanes * lanes
+    };
+
+    let laser_distance = |side: usize, section: u32, ry: u32| {
+        let section = chart.note.laser[side].iter().find(|s| s.0 == section)?;
+        let point = section.1.iter().find(|p| p.ry == ry)?;
+        let wide = section.wide() == 2;
+        let lane_distance = |v: f64| (laser_lane(v, wide) - lane).abs();
+        let closest = match point.vf {
+            Some(vf) if lane_distance(vf) < lane_distance(point.v) => vf,
+            _ => point.v,
+        };
+        Some(distance(section.0 + ry, laser_lane(closest, wide)))
+    };
+
+    SelectionArea::around(tick, lane, chart.beat.resolution)
+        .objects(chart)
+        .into_iter()
+        .filter(|o| !locks.is_locked(o))
+        .filter_map(|object| {
+            let d = match object {
+                ObjectId::Interval {
+                    fx,
+                    lane: note_lane,
+                    y,
+                } => {
+                    let notes = if fx {
+                        &chart.note.fx[..]
+                    } else {
+                        &chart.note.bt[..]
+                    };
+                    let note = notes[note_lane].iter().find(|n| n.y == y)?;
+                    let center = if fx {
+                        note_lane as f32 * 2.0 + 2.0
+                    } else {
+                        note_lane as f32 + 1.5
+                    };
+                    distance(tick.max(y).min(y + note.l), center)
+                }
+                ObjectId::LaserPoint { side, section, ry } => laser_distance(side, section, ry)?,
+                ObjectId::LaserSection { .. } => return None,
+                ObjectId::Bpm { tick: bpm_tick } => distance(bpm_tick, 6.0),
+                ObjectId::TimeSig { measure } => distance(chart.measure_to_tick(measure), 6.0),
+            };
+            Some((object, d))
+        })
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(object, _)| object)
+}
+
+/// The set of selected chart objects, kept in `MainState` so every command can use it.
+#[derive(Default, Clone)]
+pub struct Selection {
+    objects: BTreeSet<ObjectId>,
+}
+
+impl Selection {
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ObjectId> {
+        self.objects.iter()
+    }
+
+    pub fn contains(&self, object: &ObjectId) -> bool {
+        self.objects.contains(object)
+    }
+
+    pub fn clear(&mut self) {
+        self.objects.clear()
+    }
+
+    pub fn extend(&mut self, objects: impl IntoIterator<Item = ObjectId>) {
+        self.objects.extend(objects)
+    }
+
+    /// Drops objects that no longer exist, after an undo for example.
+    pub fn retain_existing(&mut self, chart: &Chart) {
+        self.objects.retain(|o| o.exists(chart));
+    }
+
+    /// Drops objects on locked layers.
+    pub fn retain_unlocked(&mut self, locks: &LayerLocks) {
+        self.objects.retain(|o| !locks.is_locked(o));
+    }
+
+    /// Replaces the ids of objects that were moved.
+    pub fn replace(&mut self, moved: &[(ObjectId, ObjectId)]) {
+        for (old, _) in moved {
+            self.objects.remove(old);
+        }
+        self.objects.extend(moved.iter().map(|(_, new)| *new));
+    }
+
+    /// The first and last tick covered by the selected objects.
+    pub fn tick_range(&self, chart: &Chart) -> Option<(u32, u32)> {
+        self.objects
+            .iter()
+            .filter_map(|object| match *object {
+                ObjectId::Interval { fx, lane, y } => {
+                    let notes = if fx {
+                        &chart.note.fx[lane]
+                    } else {
+                        &chart.note.bt[lane]
+                    };
+                    let index = notes.binary_search_by_key(&y, |n| n.y).ok()?;
+                    Some((y, y + notes[index].l))
+                }
+                ObjectId::LaserSection { .. } => None,
+                ObjectId::LaserPoint { section, ry, .. } => Some((section + ry, section + ry)),
+                ObjectId::Bpm { tick } => Some((tick, tick)),
+                ObjectId::TimeSig { measure } => {
+                    let tick = chart.measure_to_tick(measure);
+                    Some((tick, tick))
+                }
+            })
+            .reduce(|a, b| (a.0.min(b.0), a.1.max(b.1)))
+    }
+
+    pub fn draw(&self, state: &MainState, painter: &Painter) {
+        for object in &self.objects {
+            draw_object(state, painter, *object, *object, HIGHLIGHT_COLOR);
+        }
+    }
+}
+
+/// Outlines the object `shape` at the position of `at`, used to preview moves before
+/// they are made.
+pub fn draw_object(
+    state: &MainState,
+    painter: &Painter,
+    shape: ObjectId,
+    at: ObjectId,
+    color: Color32,
+) {
+    let screen = &state.screen;
+    let lane_width = screen.lane_width();
+    let stroke = Stroke::new(2.0, color);
+    let chart = &state.chart;
+
+    match (shape, at) {
+        (
+            ObjectId::Interval { fx, lane, y },
+            ObjectId::Interval {
+                lane: at_lane,
+                y: at_y,
+                ..
+            },
+        ) => {
+            let notes = if fx { &chart.note.fx } else { &chart.note.bt };
+            let note = match notes[lane].binary_search_by_key(&y, |n| n.y) {
+                Ok(index) => Interval {
+                    y: at_y,
+                    l: notes[lane][index].l,
+                },
+                Err(_) => return,
+            };
+            let (lane_x, w) = if fx {
+                ((at_lane as f32 * 2.0 + 1.0) * lane_width, lane_width * 2.0)
+            } else {
+                ((at_lane as f32 + 1.0) * lane_width, lane_width)
+            };
+
+            if note.l == 0 {
+                let (x, y) = screen.tick_to_pos(note.y);
+                let h = -2.0 * screen.note_height_mult();
+                let x = x + screen.track_width / 2.0 + lane_x;
+                painter.rect_stroke(rect_xy_wh([x, y, w, h]), 0.0, stroke);
+            } else {
+                for (x, y, h, _) in screen.interval_to_ranges(&note) {
+                    let x = x + screen.track_width / 2.0 + lane_x;
+                    painter.rect_stroke(rect_xy_wh([x, y, w, h]), 0.0, stroke);
+                }
+            }
+        }
+        (
+            ObjectId::LaserPoint { side, section, ry },
+            ObjectId::LaserPoint {
+                section: at_section,
+                ry: at_ry,
+                ..
+            },
+        ) => {
+            let section = match chart.note.laser[side].iter().find(|s| s.0 == section) {
+                Some(section) => section,
+                None => return,
+            };
+            let point = match section.1.iter().find(|p| p.ry == ry) {
+                Some(point) => point,
+                None => return,
+            };
+            let (x, y) = screen.tick_to_pos(at_section + at_ry);
+            let x = x
+                + screen.track_width / 2.0
+                + laser_lane(point.v, section.wide() == 2) * lane_width;
+            painter.circle_stroke(pos2(x, y), 4.0, stroke);
+        }
+        (_, ObjectId::Bpm { tick }) => state.draw_cursor_line(painter, tick, color),
+        (_, ObjectId::TimeSig { measure }) => {
+            state.draw_cursor_line(painter, chart.measure_to_tick(measure), color)
+        }
+        // Sections are shown through their points
+        _ => {}
+    }
+}
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+
+use crate::i18n;
+
+/// Divisions of a whole note that can be snapped to, the triplet divisions included.
+pub const DIVISIONS: [u32; 10] = [4, 8, 12, 16, 24, 32, 48, 64, 96, 192];
+
+/// The grid that clicked positions are quantized to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SnapDivision {
+    /// No snapping, every tick can be used.
+    Free,
+    /// Snaps to 1/n notes.
+    Division(u32),
+}
+
+impl Default for SnapDivision {
+    fn default() -> Self {
+        SnapDivision::Division(8)
+    }
+}
+
+impl ToString for SnapDivision {
+    fn to_string(&self) -> String {
+        match self {
+            SnapDivision::Free => i18n::fl!("snap_free"),
+            SnapDivision::Division(n) => format!("1/{}", n),
+        }
+    }
+}
+
+impl SnapDivision {
+    pub fn all() -> impl Iterator<Item = SnapDivision> {
+        DIVISIONS
+            .iter()
+            .map(|&n| SnapDivision::Division(n))
+            .chain(std::iter::once(SnapDivision::Free))
+    }
+
+    /// Ticks in a whole note and the number of grid lines in it, `None` in free mode.
+    fn grid(self, resolution: u32) -> Option<(u64, u64)> {
+        match self {
+            SnapDivision::Free => None,
+            SnapDivision::Division(n) => Some((resolution as u64 * 4, n.max(1) as u64)),
+        }
+    }
+
+    /// Ticks between grid lines, `None` in free mode.
+    pub fn spacing(self, resolution: u32) -> Option<f32> {
+        self.grid(resolution)
+            .map(|(whole, n)| whole as f32 / n as f32)
+    }
+
+    /// The closest grid line at or before `tick`.
+    pub fn snap(self, tick: u32, resolution: u32) -> u32 {
+        match self.grid(resolution) {
+            Some((whole, n)) => line(whole, n, line_index(whole, n, tick)),
+            None => tick,
+        }
+    }
+
+    /// The first grid line after `tick`.
+    pub fn next(self, tick: u32, resolution: u32) -> u32 {
+        match self.grid(resolution) {
+            Some((whole, n)) => line(whole, n, line_index(whole, n, tick) + 1),
+            None => tick.saturating_add(1),
+        }
+    }
+
+    /// The last grid line before `tick`, or 0.
+    pub fn previous(self, tick: u32, resolution: u32) -> u32 {
+        match self.grid(resolution) {
+            Some((whole, n)) => {
+                let index = line_index(whole, n, tick);
+                if line(whole, n, index) < tick {
+                    line(whole, n, index)
+                } else {
+                    line(whole, n, index.saturating_sub(1))
+                }
+            }
+            None => tick.saturating_sub(1),
+        }
+    }
+
+    /// Grid lines from `start` to `end`, none in free mode.
+    pub fn lines(self, start: u32, end: u32, resolution: u32) -> impl Iterator<Item = u32> {
+        let (whole, n, first, last) = match self.grid(resolution) {
+            Some((whole, n)) => (
+                whole,
+                n,
+                (start as u64 * n + whole - 1) / whole,
+                line_index(whole, n, end),
+            ),
+            None => (1, 1, 1, 0),
+        };
+
+        (first..=last).map(move |i| line(whole, n, i))
+    }
+
+    pub fn finer(self) -> Self {
+        match self {
+            SnapDivision::Division(n) => DIVISIONS
+                .iter()
+                .find(|&&d| d > n)
+                .map_or(SnapDivision::Free, |&d| SnapDivision::Division(d)),
+            SnapDivision::Free => SnapDivision::Free,
+        }
+    }
+
+    pub fn coarser(self) -> Self {
+        match self {
+            SnapDivision::Division(n) => DIVISIONS
+                .iter()
+                .rev()
+                .find(|&&d| d < n)
+                .map_or(self, |&d| SnapDivision::Division(d)),
+            SnapDivision::Free => SnapDivision::Division(DIVISIONS[DIVISIONS.len() - 1]),
+        }
+    }
+}
+
+/// Grid lines are rounded down to whole ticks when a division doesn't fit the resolution.
+fn line(whole: u64, n: u64, index: u64) -> u32 {
+    (index * whole / n) as u32
+}
+
+/// Index of the last grid line at or before `tick`.
+fn line_index(whole: u64, n: u64, tick: u32) -> u64 {
+    ((tick as u64 + 1) * n + whole - 1) / whole - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snap_to_division() {
+        let eighth = SnapDivision::Division(8);
+
+        assert_eq!(eighth.snap(0, 240), 0);
+        assert_eq!(eighth.snap(119, 240), 0);
+        assert_eq!(eighth.snap(120, 240), 120);
+        assert_eq!(eighth.snap(1000, 240), 960);
+        assert_eq!(SnapDivision::Division(4).snap(1919, 240), 1680);
+    }
+
+    #[test]
+    fn snap_to_division_that_does_not_fit_the_resolution() {
+        let twelfth = SnapDivision::Division(12);
+
+        // Lines are at 0, 33, 66 and 100 in a whole note of 400 ticks
+        assert_eq!(twelfth.snap(32, 100), 0);
+        assert_eq!(twelfth.snap(33, 100), 33);
+        assert_eq!(twelfth.snap(65, 100), 33);
+        assert_eq!(twelfth.snap(66, 100), 66);
+        assert_eq!(twelfth.snap(100, 100), 100);
+        assert_eq!(twelfth.snap(400, 100), 400);
+    }
+
+    #[test]
+    fn snap_free() {
+        assert_eq!(SnapDivision::Free.snap(123, 240), 123);
+        assert_eq!(SnapDivision::Free.next(123, 240), 124);
+        assert_eq!(SnapDivision::Free.previous(0, 240), 0);
+    }
+
+    #[test]
+    fn next_and_previous_line() {
+        let eighth = SnapDivision::Division(8);
+
+        assert_eq!(eighth.next(0, 240), 120);
+        assert_eq!(eighth.next(100, 240), 120);
+        assert_eq!(eighth.previous(120, 240), 0);
+        assert_eq!(eighth.previous(130, 240), 120);
+        assert_eq!(eighth.previous(0, 240), 0);
+    }
+
+    #[test]
+    fn lines_in_range() {
+        let quarter = SnapDivision::Division(4);
+
+        assert_eq!(
+            quarter.lines(0, 960, 240).collect::<Vec<_>>(),
+            vec![0, 240, 480, 720, 960]
+        );
+        assert_eq!(quarter.lines(1, 479, 240).collect::<Vec<_>>(), vec![240]);
+        assert_eq!(SnapDivision::Free.lines(0, 960, 240).count(), 0);
+    }
+
+    #[test]
+    fn finer_and_coarser() {
+        assert_eq!(
+            SnapDivision::Division(8).finer(),
+            SnapDivision::Division(12)
+        );
+        assert_eq!(SnapDivision::Division(192).finer(), SnapDivision::Free);
+        assert_eq!(SnapDivision::Free.coarser(), SnapDivision::Division(192));
+        assert_eq!(
+            SnapDivision::Division(4).coarser(),
+            SnapDivision::Division(4)
+        );
+    }
+}
@@ -1,22 +1,29 @@
+use crate::chart_edit::ChartEdit;
 use crate::i18n;
 use crate::tools::CursorObject;
+use crate::transform;
 use crate::{
     action_stack::ActionStack,
     chart_editor::{MainState, ScreenState},
 };
-use anyhow::{bail, Result};
-use eframe::egui::{self, Color32, Context, DragValue, Label, Painter, Pos2, Window};
+use anyhow::Result;
+use eframe::egui::{self, Color32, Context, DragValue, Label, Painter, Pos2, Ui, Window};
 use kson::Chart;
 enum CursorToolStates {
     None,
     Add(u32),
-    Edit(usize),
+    /// Editing the change at the given tick or measure
+    Edit(u32),
 }
 
 pub struct BpmTool {
     bpm: f64,
     state: CursorToolStates,
     cursor_tick: u32,
+    /// Tick the context menu was opened at
+    context_tick: u32,
+    /// Move the following objects so they keep their time in milliseconds
+    keep_time: bool,
 }
 
 impl BpmTool {
@@ -25,6 +32,33 @@ impl BpmTool {
             bpm: 120.0,
             state: CursorToolStates::None,
             cursor_tick: 0,
+            context_tick: 0,
+            keep_time: false,
+        }
+    }
+
+    /// Sets the BPM change at `tick` while keeping the following objects at the same time,
+    /// objects that the change moves off the snap grid are reported and selected.
+    fn retime(state: &mut MainState, tick: u32, bpm: f64) {
+        state.actions.new_action(
+            i18n::fl!("edit_bpm_change_keeping_time"),
+            ChartEdit::SetBpmKeepingTime { tick, bpm },
+        );
+
+        // The chart in the state is still the one from before the edit
+        let off_grid = match state.actions.current() {
+            Ok(retimed) => transform::off_grid(&state.chart, retimed, tick, state.snap),
+            Err(e) => {
+                state.notifications.error(&e);
+                return;
+            }
+        };
+        if !off_grid.is_empty() {
+            state
+                .notifications
+                .info(i18n::fl!("retimed_off_grid", count = off_grid.len()));
+            state.selection.clear();
+            state.selection.extend(off_grid);
         }
     }
 }
@@ -42,9 +76,9 @@ impl CursorObject for BpmTool {
     ) {
         if let CursorToolStates::None = self.state {
             //check for bpm changes on selected tick
-            for (i, change) in chart.beat.bpm.iter().enumerate() {
+            for change in &chart.beat.bpm {
                 if change.0 == tick {
-                    self.state = CursorToolStates::Edit(i);
+                    self.state = CursorToolStates::Edit(tick);
                     self.bpm = change.1;
                     return;
                 }
@@ -54,6 +88,43 @@ impl CursorObject for BpmTool {
         }
     }
 
+    fn secondary_click(
+        &mut self,
+        _screen: ScreenState,
+        tick: u32,
+        _tick_f: f64,
+        _lane: f32,
+        _chart: &Chart,
+        _actions: &mut ActionStack<Chart>,
+        _pos: Pos2,
+    ) {
+        self.context_tick = tick;
+    }
+
+    fn context_menu(&mut self, state: &mut MainState, ui: &mut Ui) {
+        if !matches!(self.state, CursorToolStates::None) {
+            return;
+        }
+
+        let tick = self.context_tick;
+        ui.separator();
+        match state.chart.beat.bpm.binary_search_by_key(&tick, |b| b.0) {
+            Ok(index) => {
+                if ui.button(i18n::fl!("edit_bpm_change")).clicked() {
+                    self.state = CursorToolStates::Edit(tick);
+                    self.bpm = state.chart.beat.bpm[index].1;
+                    ui.close_menu();
+                }
+            }
+            Err(_) => {
+                if ui.button(i18n::fl!("add_bpm_change_here")).clicked() {
+                    self.state = CursorToolStates::Add(tick);
+                    ui.close_menu();
+                }
+            }
+        }
+    }
+
     fn update(&mut self, tick: u32, _tick_f: f64, _lane: f32, _pos: Pos2, _chart: &Chart) {
         if let CursorToolStates::None = self.state {
             self.cursor_tick = tick;
@@ -70,38 +141,29 @@ impl CursorObject for BpmTool {
             CursorToolStates::None => None,
             CursorToolStates::Add(tick) => {
                 Some(Box::new(move |a: &mut ActionStack<Chart>, bpm: f64| {
-                    let v = bpm;
-                    let y = tick;
-
-                    let new_action = a.new_action();
-
-                    new_action.description = String::from(i18n::fl!("add_bpm_change"));
-                    new_action.action = Box::new(move |c| {
-                        c.beat.bpm.push((y, v));
-                        c.beat.bpm.sort_by(|a, b| a.0.cmp(&b.0));
-                        Ok(())
-                    });
+                    a.new_merging_action(
+                        &format!("bpm:{}", tick),
+                        i18n::fl!("add_bpm_change"),
+                        ChartEdit::AddBpm { tick, bpm },
+                    );
                 }))
             }
-            CursorToolStates::Edit(index) => {
+            CursorToolStates::Edit(tick) => {
                 Some(Box::new(move |a: &mut ActionStack<Chart>, bpm: f64| {
-                    let v = bpm;
-
-                    let new_action = a.new_action();
-                    new_action.description = String::from(i18n::fl!("edit_bpm_change"));
-                    new_action.action = Box::new(move |c| {
-                        if let Some(change) = c.beat.bpm.get_mut(index) {
-                            change.1 = v;
-                            Ok(())
-                        } else {
-                            bail!("Tried to edit non existing BPM Change")
-                        }
-                    });
+                    a.new_merging_action(
+                        &format!("bpm:{}", tick),
+                        i18n::fl!("edit_bpm_change"),
+                        ChartEdit::EditBpm { tick, bpm },
+                    );
                 }))
             }
         };
 
         if let Some(complete) = complete_func {
+            let tick = match self.state {
+                CursorToolStates::Add(tick) | CursorToolStates::Edit(tick) => tick,
+                CursorToolStates::None => 0,
+            };
             let mut bpm = self.bpm as f32;
             Window::new(i18n::fl!("change_bpm"))
                 .title_bar(true)
@@ -114,13 +176,20 @@ impl CursorObject for BpmTool {
                         self.bpm = bpm as f64;
 
                         ui.end_row();
+                        ui.checkbox(&mut self.keep_time, i18n::fl!("keep_real_time"));
                         ui.end_row();
 
                         if ui.button(i18n::fl!("cancel")).clicked() {
                             self.state = CursorToolStates::None;
                         }
                         if ui.button(i18n::fl!("ok")).clicked() {
-                            complete(&mut state.actions, bpm as f64);
+                            if self.keep_time {
+                                BpmTool::retime(state, tick, bpm as f64);
+                            } else {
+                                complete(&mut state.actions, bpm as f64);
+                            }
+                            // Each confirmed change is its own undo step
+                            state.actions.seal();
                             self.state = CursorToolStates::None;
                         }
                     });
@@ -138,13 +207,11 @@ impl CursorObject for BpmTool {
         actions: &mut ActionStack<Chart>,
         _pos: Pos2,
     ) {
-        if let Ok(index) = chart.beat.bpm.binary_search_by_key(&tick, |f| f.0) {
-            let new_action = actions.new_action();
-            new_action.description = i18n::fl!("remove_bpm_change").into();
-            new_action.action = Box::new(move |chart: &mut Chart| {
-                chart.beat.bpm.remove(index);
-                Ok(())
-            })
+        if chart.beat.bpm.binary_search_by_key(&tick, |f| f.0).is_ok() {
+            actions.new_action(
+                i18n::fl!("remove_bpm_change"),
+                ChartEdit::RemoveBpm { tick },
+            );
         }
     }
 }
@@ -153,6 +220,8 @@ pub struct TimeSigTool {
     ts: kson::TimeSignature,
     state: CursorToolStates,
     cursor_tick: u32,
+    /// Tick the context menu was opened at
+    context_tick: u32,
 }
 
 impl TimeSigTool {
@@ -161,6 +230,7 @@ impl TimeSigTool {
             ts: kson::TimeSignature(4, 4),
             state: CursorToolStates::None,
             cursor_tick: 0,
+            context_tick: 0,
         }
     }
 }
@@ -184,7 +254,7 @@ impl CursorObject for TimeSigTool {
                 .time_sig
                 .binary_search_by(|tsc| tsc.0.cmp(&measure))
             {
-                self.state = CursorToolStates::Edit(idx);
+                self.state = CursorToolStates::Edit(measure);
                 self.ts = chart.beat.time_sig.get(idx).unwrap().1;
             } else {
                 self.state = CursorToolStates::Add(measure);
@@ -204,13 +274,62 @@ impl CursorObject for TimeSigTool {
         _pos: Pos2,
     ) {
         let measure = chart.tick_to_measure(tick);
-        if let Ok(index) = chart.beat.time_sig.binary_search_by_key(&measure, |f| f.0) {
-            let new_action = actions.new_action();
-            new_action.description = i18n::fl!("remove_time_signature_change").into();
-            new_action.action = Box::new(move |chart: &mut Chart| {
-                chart.beat.time_sig.remove(index);
-                Ok(())
-            })
+        if chart
+            .beat
+            .time_sig
+            .binary_search_by_key(&measure, |f| f.0)
+            .is_ok()
+        {
+            actions.new_action(
+                i18n::fl!("remove_time_signature_change"),
+                ChartEdit::RemoveTimeSig { measure },
+            );
+        }
+    }
+
+    fn secondary_click(
+        &mut self,
+        _screen: ScreenState,
+        tick: u32,
+        _tick_f: f64,
+        _lane: f32,
+        _chart: &Chart,
+        _actions: &mut ActionStack<Chart>,
+        _pos: Pos2,
+    ) {
+        self.context_tick = tick;
+    }
+
+    fn context_menu(&mut self, state: &mut MainState, ui: &mut Ui) {
+        if !matches!(self.state, CursorToolStates::None) {
+            return;
+        }
+
+        let measure = state.chart.tick_to_measure(self.context_tick);
+        ui.separator();
+        match state
+            .chart
+            .beat
+            .time_sig
+            .binary_search_by_key(&measure, |t| t.0)
+        {
+            Ok(index) => {
+                if ui.button(i18n::fl!("edit_time_signature_change")).clicked() {
+                    self.state = CursorToolStates::Edit(measure);
+                    self.ts = state.chart.beat.time_sig[index].1;
+                    ui.close_menu();
+                }
+            }
+            Err(_) => {
+                if ui
+                    .button(i18n::fl!("add_time_signature_change_here"))
+                    .clicked()
+                {
+                    self.state = CursorToolStates::Add(measure);
+                    self.ts = kson::TimeSignature(4, 4);
+                    ui.close_menu();
+                }
+            }
         }
     }
 
@@ -233,29 +352,24 @@ impl CursorObject for TimeSigTool {
         {
             CursorToolStates::None => None,
             CursorToolStates::Add(measure) => Some(Box::new(move |a, ts| {
-                let v = kson::TimeSignature(ts[0] as u32, ts[1] as u32);
-                let idx = measure;
-
-                let new_action = a.new_action();
-                new_action.description = String::from(i18n::fl!("add_time_signature_change"));
-                new_action.action = Box::new(move |c| {
-                    c.beat.time_sig.push((idx, v));
-                    c.beat.time_sig.sort_by(|a, b| a.0.cmp(&b.0));
-                    Ok(())
-                });
+                a.new_merging_action(
+                    &format!("time_sig:{}", measure),
+                    i18n::fl!("add_time_signature_change"),
+                    ChartEdit::AddTimeSig {
+                        measure,
+                        time_sig: kson::TimeSignature(ts[0] as u32, ts[1] as u32),
+                    },
+                );
             })),
-            CursorToolStates::Edit(index) => Some(Box::new(move |a, ts| {
-                let new_action = a.new_action();
-                new_action.description = String::from(i18n::fl!("edit_time_signature_change"));
-                new_action.action = Box::new(move |c| {
-                    if let Some(change) = c.beat.time_sig.get_mut(index) {
-                        change.1 .0 = ts[0] as u32;
-                        change.1 .1 = ts[1] as u32;
-                        Ok(())
-                    } else {
-                        bail!("Tried to edit non existing Time Signature Change")
-                    }
-                });
+            CursorToolStates::Edit(measure) => Some(Box::new(move |a, ts| {
+                a.new_merging_action(
+                    &format!("time_sig:{}", measure),
+                    i18n::fl!("edit_time_signature_change"),
+                    ChartEdit::EditTimeSig {
+                        measure,
+                        time_sig: kson::TimeSignature(ts[0] as u32, ts[1] as u32),
+                    },
+                );
             })),
         };
 
@@ -279,6 +393,7 @@ impl CursorObject for TimeSigTool {
 
                         if ui.button(i18n::fl!("ok")).clicked() {
                             complete(&mut state.actions, [ts_n as i32, ts_d as i32]);
+                            state.actions.seal();
                             self.state = CursorToolStates::None;
                         }
                         if ui.button(i18n::fl!("cancel")).clicked() {
@@ -1,4 +1,6 @@
+use crate::chart_edit::ChartEdit;
 use crate::i18n;
+use crate::overlap;
 use crate::tools::CursorObject;
 use crate::utils::Overlaps;
 use crate::Modifiers;
@@ -8,15 +10,59 @@ use crate::{
     rect_xy_wh,
 };
 use anyhow::Result;
-use eframe::egui::{Painter, Pos2, Rgba, Shape};
+use eframe::egui::{Context, Painter, Pos2, Rgba, Shape};
 use kson::{Chart, Interval};
 
+/// The FX (0-1) or BT (0-3) lane at a position in lane space.
+pub fn button_lane(fx: bool, lane: f32) -> usize {
+    if fx {
+        if lane < 3.0 {
+            0
+        } else {
+            1
+        }
+    } else {
+        (lane as usize).max(1).min(4) - 1
+    }
+}
+
+/// Short name of a BT (A-D) or FX (L, R) lane.
+pub fn lane_name(fx: bool, lane: usize) -> String {
+    if fx {
+        format!("FX-{}", if lane == 0 { "L" } else { "R" })
+    } else {
+        format!(
+            "BT-{}",
+            std::char::from_u32('A' as u32 + lane as u32).unwrap_or_default()
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum HoldEnd {
+    Head,
+    Tail,
+}
+
+/// A hold note being resized by dragging one of its ends.
+struct Resize {
+    original: Interval,
+    end: HoldEnd,
+    /// The range the note can cover without touching its neighbours
+    bounds: (u32, u32),
+}
+
 //structs for cursor objects
 pub struct ButtonInterval {
     pressed: bool,
     fx: bool,
     interval: Interval,
     lane: usize,
+    /// End of a hold note under the cursor that can be dragged
+    hovered_end: Option<(Interval, HoldEnd)>,
+    resize: Option<Resize>,
+    /// Note drawn since the last ui update and its lane, placed in `draw_ui`
+    placed: Option<(usize, Interval)>,
 }
 
 impl ButtonInterval {
@@ -26,6 +72,77 @@ impl ButtonInterval {
             fx,
             interval: Interval { y: 0, l: 0 },
             lane: 0,
+            hovered_end: None,
+            resize: None,
+            placed: None,
+        }
+    }
+
+    fn lane_notes<'a>(&self, chart: &'a Chart) -> &'a Vec<Interval> {
+        if self.fx {
+            &chart.note.fx[self.lane]
+        } else {
+            &chart.note.bt[self.lane]
+        }
+    }
+
+    /// Finds a hold note end in the current lane close enough to `tick` to be grabbed.
+    fn hold_end_at(&self, tick_f: f64, chart: &Chart) -> Option<(Interval, HoldEnd)> {
+        let margin = chart.beat.resolution as f64 / 8.0;
+        let near = |y: u32| (y as f64 - tick_f).abs() <= margin;
+
+        self.lane_notes(chart)
+            .iter()
+            .filter(|n| n.l > 0)
+            .find_map(|n| {
+                if near(n.y + n.l) {
+                    Some((*n, HoldEnd::Tail))
+                } else if near(n.y) {
+                    Some((*n, HoldEnd::Head))
+                } else {
+                    None
+                }
+            })
+    }
+
+    fn add_description(&self, lane: usize) -> String {
+        if self.fx {
+            i18n::fl!(
+                "add_fx",
+                side = if lane == 0 {
+                    i18n::fl!("left")
+                } else {
+                    i18n::fl!("right")
+                }
+            )
+        } else {
+            i18n::fl!(
+                "add_bt",
+                lane = std::char::from_u32('A' as u32 + lane as u32)
+                    .unwrap_or_default()
+                    .to_string()
+            )
+        }
+    }
+
+    fn resized(resize: &Resize, tick: u32) -> Interval {
+        let Resize {
+            original, bounds, ..
+        } = resize;
+        let end = original.y + original.l;
+
+        match resize.end {
+            HoldEnd::Head => {
+                let y = tick.max(bounds.0).min(end);
+                Interval { y, l: end - y }
+            }
+            HoldEnd::Tail => {
+                let end = tick.max(original.y).min(bounds.1);
+                Interval {
+                    y: original.y,
+                    l: end - original.y,
+                }
+            }
         }
     }
 }
@@ -37,17 +154,34 @@ impl CursorObject for ButtonInterval {
         tick: u32,
         _tick_f: f64,
         lane: f32,
-        _chart: &Chart,
+        chart: &Chart,
         _actions: &mut ActionStack<Chart>,
         _pos: Pos2,
         _modifiers: &Modifiers,
     ) {
-        self.pressed = true;
-        if self.fx {
-            self.lane = if lane < 3.0 { 0 } else { 1 };
-        } else {
-            self.lane = (lane as usize).max(1).min(4) - 1;
+        self.lane = button_lane(self.fx, lane);
+
+        if let Some((original, end)) = self.hovered_end.take() {
+            let notes = self.lane_notes(chart);
+            let bounds = match notes.binary_search_by_key(&original.y, |n| n.y) {
+                Ok(index) => (
+                    index
+                        .checked_sub(1)
+                        .map_or(0, |i| notes[i].y + notes[i].l + 1),
+                    notes.get(index + 1).map_or(u32::MAX, |n| n.y - 1),
+                ),
+                Err(_) => return,
+            };
+            self.resize = Some(Resize {
+                original,
+                end,
+                bounds,
+            });
+            self.interval = original;
+            return;
         }
+
+        self.pressed = true;
         self.interval.y = tick;
     }
 
@@ -65,15 +199,7 @@ impl CursorObject for ButtonInterval {
             return;
         }
 
-        let lane = if self.fx {
-            if lane < 3.0 {
-                0
-            } else {
-                1
-            }
-        } else {
-            (lane as usize).max(1).min(4) - 1
-        };
+        let lane = button_lane(self.fx, lane);
 
         //hit test
         let lane_data = if self.fx {
@@ -82,26 +208,17 @@ impl CursorObject for ButtonInterval {
             &chart.note.bt[lane]
         };
 
-        let index = lane_data
-            .iter()
-            .enumerate()
-            .find(|(_, n)| n.contains(tick))
-            .map(|(i, _)| i);
-
-        if let Some(index) = index {
-            // remove found index
-            let new_action = actions.new_action();
+        if let Some(note) = lane_data.iter().find(|n| n.contains(tick)) {
+            // remove found note
             let fx = self.fx;
-            new_action.description = i18n::fl!("remove_note", lane = if fx { "FX" } else { "BT" });
-            new_action.action = Box::new(move |chart: &mut Chart| {
-                if fx {
-                    chart.note.fx[lane].remove(index);
-                } else {
-                    chart.note.bt[lane].remove(index);
-                }
-
-                Ok(())
-            });
+            actions.new_action(
+                i18n::fl!("remove_note", lane = if fx { "FX" } else { "BT" }),
+                ChartEdit::RemoveInterval {
+                    fx,
+                    lane,
+                    y: note.y,
+                },
+            );
         }
     }
 
@@ -115,6 +232,23 @@ impl CursorObject for ButtonInterval {
         actions: &mut ActionStack<Chart>,
         _pos: Pos2,
     ) {
+        if let Some(resize) = self.resize.take() {
+            let interval = Self::resized(&resize, tick);
+            if interval.y != resize.original.y || interval.l != resize.original.l {
+                actions.new_action(
+                    i18n::fl!("resize_note", lane = if self.fx { "FX" } else { "BT" }),
+                    ChartEdit::ResizeInterval {
+                        fx: self.fx,
+                        lane: self.lane,
+                        y: resize.original.y,
+                        interval,
+                    },
+                );
+            }
+            self.interval = Interval { y: tick, l: 0 };
+            return;
+        }
+
         if !self.pressed {
             return;
         }
@@ -125,51 +259,21 @@ impl CursorObject for ButtonInterval {
             self.interval.l = tick - self.interval.y;
         }
         let v = std::mem::replace(&mut self.interval, Interval { y: 0, l: 0 });
-        if self.fx {
-            let l = self.lane;
-
-            let new_action = actions.new_action();
-            new_action.description = i18n::fl!(
-                "add_fx",
-                side = if self.lane == 0 {
-                    i18n::fl!("left")
-                } else {
-                    i18n::fl!("right")
-                }
-            );
-            new_action.action = Box::new(move |edit_chart: &mut Chart| {
-                edit_chart.note.fx[l].push(v);
-                edit_chart.note.fx[l].sort_by(|a, b| a.y.partial_cmp(&b.y).unwrap());
-                Ok(())
-            });
-        } else {
-            let l = self.lane;
-
-            let new_action = actions.new_action();
-            new_action.description = i18n::fl!(
-                "add_bt",
-                lane = std::char::from_u32('A' as u32 + self.lane as u32)
-                    .unwrap_or_default()
-                    .to_string()
-            );
-            new_action.action = Box::new(move |edit_chart: &mut Chart| {
-                edit_chart.note.bt[l].push(v);
-                edit_chart.note.bt[l].sort_by(|a, b| a.y.partial_cmp(&b.y).unwrap());
-                Ok(())
-            });
-        }
+        self.placed = Some((self.lane, v));
         self.pressed = false;
         self.lane = 0;
     }
 
-    fn update(&mut self, tick: u32, _tick_f: f64, lane: f32, _pos: Pos2, _chart: &Chart) {
+    fn update(&mut self, tick: u32, tick_f: f64, lane: f32, _pos: Pos2, chart: &Chart) {
+        if let Some(resize) = &self.resize {
+            self.interval = Self::resized(resize, tick);
+            return;
+        }
+
         if !self.pressed {
             self.interval.y = tick;
-            if self.fx {
-                self.lane = if lane < 3.0 { 0 } else { 1 };
-            } else {
-                self.lane = (lane as usize).max(1).min(4) - 1;
-            }
+            self.lane = button_lane(self.fx, lane);
+            self.hovered_end = self.hold_end_at(tick_f, chart);
         }
         if self.interval.y >= tick {
             self.interval.l = 0;
@@ -184,6 +288,37 @@ impl CursorObject for ButtonInterval {
         } else {
             Rgba::from_rgba_premultiplied(1.0, 1.0, 1.0, 0.5)
         };
+
+        if let Some((note, end)) = self.hovered_end {
+            let tick = match end {
+                HoldEnd::Head => note.y,
+                HoldEnd::Tail => note.y + note.l,
+            };
+            let (x, y) = state.screen.tick_to_pos(tick);
+            let (x, w) = if self.fx {
+                (
+                    x + self.lane as f32 * state.screen.lane_width() * 2.0
+                        + 2.0 * self.lane as f32
+                        + state.screen.lane_width()
+                        + state.screen.track_width / 2.0,
+                    state.screen.track_width as f32 / 3.0 - 1.0,
+                )
+            } else {
+                (
+                    x + self.lane as f32 * state.screen.lane_width()
+                        + 1.0 * self.lane as f32
+                        + state.screen.lane_width()
+                        + state.screen.track_width / 2.0,
+                    state.screen.track_width as f32 / 6.0 - 2.0,
+                )
+            };
+            painter.rect_filled(
+                rect_xy_wh([x, y - 3.0, w, 6.0]),
+                2.0,
+                Rgba::from_rgb(1.0, 1.0, 0.0),
+            );
+            return Ok(());
+        }
         if self.interval.l == 0 {
             let (x, y) = state.screen.tick_to_pos(self.interval.y);
 
@@ -237,4 +372,19 @@ impl CursorObject for ButtonInterval {
             Ok(())
         }
     }
+
+    fn draw_ui(&mut self, state: &mut MainState, _ctx: &Context) {
+        if let Some((lane, interval)) = self.placed.take() {
+            match overlap::place_interval(
+                &state.chart,
+                self.fx,
+                lane,
+                interval,
+                state.overlap_policy,
+            ) {
+                Some(edit) => state.actions.new_action(self.add_description(lane), edit),
+                None => state.notifications.info(i18n::fl!("overlap_rejected")),
+            }
+        }
+    }
 }
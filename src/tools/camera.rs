@@ -3,9 +3,11 @@ use eframe::{
     epaint::Rgba,
 };
 
+use crate::chart_edit::ChartEdit;
 use crate::i18n;
 use glam::vec3;
 use kson::{Chart, Graph, GraphPoint, GraphSectionPoint};
+use serde::{Deserialize, Serialize};
 use std::{default::Default, f32::EPSILON, ops::Sub};
 
 use crate::camera_widget::CameraView;
@@ -13,8 +15,8 @@ use crate::chart_camera::ChartCamera;
 
 use super::CursorObject;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum CameraPaths {
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum CameraPaths {
     Zoom,
     RotationX,
 }
@@ -25,6 +27,10 @@ impl Default for CameraPaths {
     }
 }
 
+impl CameraPaths {
+    pub const ALL: [CameraPaths; 2] = [CameraPaths::Zoom, CameraPaths::RotationX];
+}
+
 impl ToString for CameraPaths {
     fn to_string(&self) -> String {
         match self {
@@ -44,12 +50,16 @@ pub struct CameraTool {
     curving_index: Option<(usize, f64, f64)>,
 }
 
+pub fn camera_graph(chart: &kson::Chart, graph: CameraPaths) -> &Vec<kson::GraphPoint> {
+    match graph {
+        CameraPaths::Zoom => &chart.camera.cam.body.zoom,
+        CameraPaths::RotationX => &chart.camera.cam.body.rotation_x,
+    }
+}
+
 impl CameraTool {
-    fn current_graph<'a>(&mut self, chart: &'a kson::Chart) -> &'a Vec<kson::GraphPoint> {
-        match self.display_line {
-            CameraPaths::Zoom => &chart.camera.cam.body.zoom,
-            CameraPaths::RotationX => &chart.camera.cam.body.rotation_x,
-        }
+    fn current_graph<'a>(&self, chart: &'a kson::Chart) -> &'a Vec<kson::GraphPoint> {
+        camera_graph(chart, self.display_line)
     }
 }
 
@@ -180,34 +190,31 @@ impl CursorObject for CameraTool {
         _tick: u32,
         _tick_f: f64,
         _lane: f32,
-        _chart: &kson::Chart,
+        chart: &kson::Chart,
         actions: &mut crate::action_stack::ActionStack<kson::Chart>,
         _pos: Pos2,
     ) {
-        if let Some((ci, a, b)) = self.curving_index {
-            let new_action = actions.new_action();
-            let active_line = self.display_line;
-            new_action.action = Box::new(move |chart| {
-                let graph = match active_line {
-                    CameraPaths::Zoom => &mut chart.camera.cam.body.zoom,
-                    CameraPaths::RotationX => &mut chart.camera.cam.body.rotation_x,
-                };
-
-                if let Some(point) = graph.get_mut(ci) {
-                    point.a = Some(a);
-                    point.b = Some(b);
-                }
-
-                Ok(())
-            });
-
-            new_action.description = i18n::fl!(
-                "edit_curve_for_camera",
-                graph = match self.display_line {
-                    CameraPaths::Zoom => i18n::fl!("radius"),
-                    CameraPaths::RotationX => i18n::fl!("angle"),
-                }
-            )
+        let curved_point = self
+            .curving_index
+            .and_then(|(ci, a, b)| self.current_graph(chart).get(ci).map(|p| (p.y, a, b)));
+
+        if let Some((y, a, b)) = curved_point {
+            actions.new_merging_action(
+                &format!("camera_curve:{:?}:{}", self.display_line, y),
+                i18n::fl!(
+                    "edit_curve_for_camera",
+                    graph = match self.display_line {
+                        CameraPaths::Zoom => i18n::fl!("radius"),
+                        CameraPaths::RotationX => i18n::fl!("angle"),
+                    }
+                ),
+                ChartEdit::SetCameraCurve {
+                    graph: self.display_line,
+                    y,
+                    a,
+                    b,
+                },
+            );
         }
 
         self.curving_index = None
@@ -266,6 +273,29 @@ impl CursorObject for CameraTool {
                     self.radius_dirty = true;
                 }
 
+                // Existing control points under the cursor are edited directly
+                let y = state.cursor_line;
+                for (graph, v, dirty) in [
+                    (CameraPaths::Zoom, self.radius, &mut self.radius_dirty),
+                    (CameraPaths::RotationX, self.angle, &mut self.angle_dirty),
+                ] {
+                    let has_point = camera_graph(&state.chart, graph)
+                        .binary_search_by_key(&y, |p| p.y)
+                        .is_ok();
+                    if *dirty && has_point {
+                        state.actions.new_merging_action(
+                            &format!("camera_value:{:?}:{}", graph, y),
+                            i18n::fl!("edit_camera_value", graph = graph.to_string()),
+                            ChartEdit::SetCameraValue {
+                                graph,
+                                y,
+                                v: v as f64,
+                            },
+                        );
+                        *dirty = false;
+                    }
+                }
+
                 ComboBox::from_label(i18n::fl!("display_line"))
                     .selected_text(self.display_line.to_string())
                     .show_ui(ui, |ui| {
@@ -282,8 +312,6 @@ impl CursorObject for CameraTool {
                     });
 
                 if ui.button(i18n::fl!("add_control_point")).clicked() {
-                    let new_action = state.actions.new_action();
-                    new_action.description = i18n::fl!("added_camera_control_point").to_string();
                     let Self {
                         angle,
                         radius,
@@ -293,31 +321,38 @@ impl CursorObject for CameraTool {
                         curving_index: _,
                     } = *self;
                     let y = state.cursor_line;
-                    new_action.action = Box::new(move |c| {
-                        if angle_dirty {
-                            c.camera.cam.body.rotation_x.push(kson::GraphPoint {
+                    let mut edits = Vec::new();
+                    if angle_dirty {
+                        edits.push(ChartEdit::AddCameraPoint {
+                            graph: CameraPaths::RotationX,
+                            point: kson::GraphPoint {
                                 y,
                                 v: angle as f64,
                                 vf: None,
                                 a: Some(0.5),
                                 b: Some(0.5),
-                            })
-                        }
-                        if radius_dirty {
-                            c.camera.cam.body.zoom.push(kson::GraphPoint {
+                            },
+                        });
+                    }
+                    if radius_dirty {
+                        edits.push(ChartEdit::AddCameraPoint {
+                            graph: CameraPaths::Zoom,
+                            point: kson::GraphPoint {
                                 y,
                                 v: radius as f64,
                                 vf: None,
                                 a: Some(0.5),
                                 b: Some(0.5),
-                            });
-                        }
+                            },
+                        });
+                    }
 
-                        //TODO: just insert sorted instead
-                        c.camera.cam.body.zoom.sort_by_key(|p| p.y);
-                        c.camera.cam.body.rotation_x.sort_by_key(|p| p.y);
-                        Ok(())
-                    });
+                    if !edits.is_empty() {
+                        state.actions.new_action(
+                            i18n::fl!("added_camera_control_point"),
+                            ChartEdit::Batch(edits),
+                        );
+                    }
 
                     self.radius_dirty = false;
                     self.angle_dirty = false;
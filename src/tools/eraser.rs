@@ -0,0 +1,168 @@
+use std::collections::BTreeSet;
+
+use crate::chart_edit::ChartEdit;
+use crate::i18n;
+use crate::selection::{self, laser_lane, laser_value_at, ObjectId, SelectionArea};
+use crate::tools::CursorObject;
+use crate::{
+    action_stack::ActionStack,
+    chart_editor::{MainState, ScreenState},
+    utils::Overlaps,
+    Modifiers,
+};
+use anyhow::Result;
+use eframe::egui::{Color32, Context, Painter, Pos2};
+use kson::{Chart, LaserSection};
+
+/// Lane of a laser section's line at `tick`.
+fn laser_lane_at(section: &LaserSection, tick: u32) -> Option<f32> {
+    let ry = tick.checked_sub(section.tick())?;
+    let v = laser_value_at(section, ry)?;
+    Some(laser_lane(v, section.wide() == 2))
+}
+
+/// Objects the eraser removes at a position. Laser sections are hit anywhere along their
+/// line, the BPM and time signature at the start of the chart are kept.
+fn objects_at(chart: &Chart, tick: u32, lane: f32) -> Vec<ObjectId> {
+    let mut objects: Vec<ObjectId> = SelectionArea::around(tick, lane, chart.beat.resolution)
+        .objects(chart)
+        .into_iter()
+        .filter_map(|o| match o {
+            ObjectId::LaserPoint { side, section, .. } => Some(ObjectId::LaserSection {
+                side,
+                tick: section,
+            }),
+            ObjectId::Bpm { tick: 0 } | ObjectId::TimeSig { measure: 0 } => None,
+            o => Some(o),
+        })
+        .collect();
+
+    for (side, sections) in chart.note.laser.iter().enumerate() {
+        let hit = sections
+            .iter()
+            .filter(|s| s.contains(tick))
+            .find(|s| laser_lane_at(s, tick).map_or(false, |l| (l - lane).abs() < 0.5));
+        if let Some(section) = hit {
+            objects.push(ObjectId::LaserSection {
+                side,
+                tick: section.tick(),
+            });
+        }
+    }
+
+    objects
+}
+
+/// Deletes everything under the pointer while dragging, the whole stroke is one action.
+/// Objects on locked layers are left alone.
+#[derive(Default)]
+pub struct EraserTool {
+    /// Objects erased by the current stroke, removed from the chart when it ends
+    erased: BTreeSet<ObjectId>,
+    erasing: bool,
+    /// A stroke ended since the last ui update, its objects are removed in `draw_ui` where
+    /// the layer locks are known
+    finished: bool,
+}
+
+impl EraserTool {
+    fn erase(&mut self, state: &mut MainState) {
+        let locks = state.layer_locks;
+        let edits: Vec<ChartEdit> = std::mem::take(&mut self.erased)
+            .into_iter()
+            .filter(|object| !locks.is_locked(object))
+            .filter_map(|object| match object {
+                ObjectId::Interval { fx, lane, y } => {
+                    Some(ChartEdit::RemoveInterval { fx, lane, y })
+                }
+                ObjectId::LaserSection { side, tick } => {
+                    Some(ChartEdit::RemoveLaserSection { side, tick })
+                }
+                ObjectId::Bpm { tick } => Some(ChartEdit::RemoveBpm { tick }),
+                ObjectId::TimeSig { measure } => Some(ChartEdit::RemoveTimeSig { measure }),
+                ObjectId::LaserPoint { .. } => None,
+            })
+            .collect();
+
+        if !edits.is_empty() {
+            state.actions.new_action(
+                i18n::fl!("erase_objects", count = edits.len()),
+                ChartEdit::Batch(edits),
+            );
+        }
+    }
+}
+
+impl CursorObject for EraserTool {
+    fn primary_click(
+        &mut self,
+        _screen: ScreenState,
+        _tick: u32,
+        tick_f: f64,
+        lane: f32,
+        chart: &Chart,
+        _actions: &mut ActionStack<Chart>,
+        _pos: Pos2,
+    ) {
+        self.erased.extend(objects_at(chart, tick_f as u32, lane));
+        self.finished = true;
+    }
+
+    fn drag_start(
+        &mut self,
+        _screen: ScreenState,
+        _tick: u32,
+        tick_f: f64,
+        lane: f32,
+        chart: &Chart,
+        _actions: &mut ActionStack<Chart>,
+        _pos: Pos2,
+        _modifiers: &Modifiers,
+    ) {
+        self.erasing = true;
+        self.erased.clear();
+        self.erased.extend(objects_at(chart, tick_f as u32, lane));
+    }
+
+    fn drag_end(
+        &mut self,
+        _screen: ScreenState,
+        _tick: u32,
+        tick_f: f64,
+        lane: f32,
+        chart: &Chart,
+        _actions: &mut ActionStack<Chart>,
+        _pos: Pos2,
+    ) {
+        if !self.erasing {
+            return;
+        }
+        self.erasing = false;
+        self.erased.extend(objects_at(chart, tick_f as u32, lane));
+        self.finished = true;
+    }
+
+    fn update(&mut self, _tick: u32, tick_f: f64, lane: f32, _pos: Pos2, chart: &Chart) {
+        if self.erasing {
+            self.erased.extend(objects_at(chart, tick_f as u32, lane));
+        }
+    }
+
+    fn draw(&self, state: &MainState, painter: &Painter) -> Result<()> {
+        for object in self
+            .erased
+            .iter()
+            .filter(|o| !state.layer_locks.is_locked(o))
+        {
+            selection::draw_object(state, painter, *object, *object, Color32::RED);
+        }
+        Ok(())
+    }
+
+    fn draw_ui(&mut self, state: &mut MainState, _ctx: &Context) {
+        if self.finished {
+            self.finished = false;
+            self.erase(state);
+        }
+    }
+}
@@ -1,4 +1,7 @@
+use crate::chart_edit::ChartEdit;
 use crate::i18n;
+use crate::overlap;
+use crate::selection::{laser_lane, laser_value_at};
 use crate::tools::CursorObject;
 use crate::Modifiers;
 use crate::{
@@ -7,7 +10,7 @@ use crate::{
     utils::Overlaps,
 };
 use anyhow::Result;
-use eframe::egui::{Painter, Pos2, Rgba, Stroke};
+use eframe::egui::{pos2, Color32, Context, Painter, Pos2, Rgba, Stroke, Ui};
 use eframe::epaint::Shape;
 use kson::{Chart, GraphSectionPoint, LaserSection};
 
@@ -15,12 +18,38 @@ pub struct LaserTool {
     right: bool,
     section: LaserSection,
     mode: LaserEditMode,
+    /// Section finished since the last ui update, placed in `draw_ui`
+    placed: Option<LaserSection>,
+    /// Tick and position the context menu was opened at
+    context: (u32, Pos2),
+}
+
+/// Name of a laser side for action descriptions.
+pub fn laser_side_name(side: usize) -> String {
+    if side == 0 {
+        i18n::fl!("left")
+    } else {
+        i18n::fl!("right")
+    }
 }
 
 #[derive(Copy, Clone)]
 struct LaserEditState {
-    section_index: usize,
+    /// Tick of the section being edited
+    section_tick: u32,
     curving_index: Option<usize>,
+    moving: Option<PointDrag>,
+}
+
+/// A point of the edited section being dragged.
+#[derive(Copy, Clone)]
+struct PointDrag {
+    index: usize,
+    /// Dragging where a slam ends instead of the point itself
+    slam_end: bool,
+    /// Ticks the point can be moved between without passing its neighbours
+    min_tick: u32,
+    max_tick: u32,
 }
 
 enum LaserEditMode {
@@ -35,6 +64,8 @@ impl LaserTool {
             right,
             mode: LaserEditMode::None,
             section: LaserSection(0, Vec::new(), 0),
+            placed: None,
+            context: (0, Pos2::ZERO),
         }
     }
 
@@ -82,14 +113,195 @@ impl LaserTool {
         }
     }
 
-    fn hit_test(&self, chart: &Chart, tick: u32) -> Option<usize> {
-        let side_index: usize = if self.right { 1 } else { 0 };
+    fn side_name(&self) -> String {
+        laser_side_name(self.side_index())
+    }
+
+    fn side_index(&self) -> usize {
+        if self.right {
+            1
+        } else {
+            0
+        }
+    }
 
-        chart.note.laser[side_index]
+    fn hit_test<'a>(&self, chart: &'a Chart, tick: u32) -> Option<&'a LaserSection> {
+        chart.note.laser[self.side_index()]
             .iter()
-            .enumerate()
-            .find(|(_, s)| s.contains(tick))
-            .map(|(i, _)| i)
+            .find(|s| s.contains(tick))
+    }
+
+    /// Screen positions of a point of the edited section, slams have two.
+    fn point_positions(&self, screen: &ScreenState, point: &GraphSectionPoint) -> Vec<Pos2> {
+        let wide = self.section.wide() == 2;
+        let (x, y) = screen.tick_to_pos(self.section.tick() + point.ry);
+        let x = x + screen.track_width / 2.0;
+        std::iter::once(point.v)
+            .chain(point.vf)
+            .map(|v| pos2(x + laser_lane(v, wide) * screen.lane_width(), y))
+            .collect()
+    }
+
+    /// Index of the point of the edited section drawn under `pos`, and whether it is
+    /// the end of a slam.
+    fn point_at(&self, screen: &ScreenState, pos: Pos2) -> Option<(usize, bool)> {
+        self.section.1.iter().enumerate().find_map(|(i, p)| {
+            self.point_positions(screen, p)
+                .iter()
+                .position(|p| p.distance(pos) < 6.0)
+                .map(|end| (i, end == 1))
+        })
+    }
+
+    /// Starts dragging a point of the edited section, it can't pass the points or
+    /// sections next to it and slam ends only move sideways.
+    fn start_point_drag(&self, chart: &Chart, index: usize, slam_end: bool) -> PointDrag {
+        let start = self.section.tick();
+        let tick = start + self.section.1[index].ry;
+        let (min_tick, max_tick) = if slam_end {
+            (tick, tick)
+        } else {
+            let sections = &chart.note.laser[self.side_index()];
+            let min_tick = match index.checked_sub(1) {
+                Some(previous) => start + self.section.1[previous].ry + 1,
+                None => sections
+                    .iter()
+                    .filter(|s| s.tick() < start)
+                    .filter_map(|s| s.last().map(|p| s.tick() + p.ry + 1))
+                    .max()
+                    .unwrap_or(0),
+            };
+            let max_tick = match self.section.1.get(index + 1) {
+                Some(next) => start + next.ry - 1,
+                None => sections
+                    .iter()
+                    .map(|s| s.tick())
+                    .find(|&t| t > start)
+                    .map_or(u32::MAX, |t| t - 1),
+            };
+            (min_tick, max_tick)
+        };
+
+        PointDrag {
+            index,
+            slam_end,
+            min_tick,
+            max_tick,
+        }
+    }
+
+    /// The edited section with a dragged point moved to `tick` and `lane`.
+    fn dragged_section(
+        original: &LaserSection,
+        drag: PointDrag,
+        tick: u32,
+        lane: f32,
+    ) -> LaserSection {
+        let mut section = original.clone();
+        let v = LaserTool::lane_to_pos(lane, section.wide());
+        let start = original.tick();
+        let index = drag.index;
+
+        // Points can't pass their neighbours in the section
+        let min_tick = match index.checked_sub(1) {
+            Some(previous) => start + original.1[previous].ry + 1,
+            None => 0,
+        };
+        let max_tick = original
+            .1
+            .get(index + 1)
+            .map_or(u32::MAX, |next| start + next.ry - 1);
+        let tick = tick
+            .max(drag.min_tick)
+            .min(drag.max_tick)
+            .max(min_tick)
+            .min(max_tick);
+
+        let point = &mut section.1[index];
+        if drag.slam_end {
+            point.vf = Some(v);
+        } else {
+            point.v = v;
+        }
+
+        if drag.slam_end {
+            return section;
+        }
+        if index == 0 {
+            // The section starts at its first point, the others keep their tick
+            section.0 = tick;
+            for (point, original_point) in section.1.iter_mut().zip(&original.1).skip(1) {
+                point.ry = start + original_point.ry - tick;
+            }
+        } else {
+            section.1[index].ry = tick - start;
+        }
+        section
+    }
+
+    /// Adds a point to the edited section at `tick` unless there already is one.
+    fn insert_point(&self, tick: u32, actions: &mut ActionStack<Chart>) {
+        let ry = match tick.checked_sub(self.section.tick()) {
+            Some(ry) => ry,
+            None => return,
+        };
+        let inside = self
+            .section
+            .last()
+            .map_or(false, |last| ry > 0 && ry < last.ry);
+        if !inside || self.section.1.iter().any(|p| p.ry == ry) {
+            return;
+        }
+        let v = match laser_value_at(&self.section, ry) {
+            Some(v) => v,
+            None => return,
+        };
+
+        actions.new_action(
+            i18n::fl!("add_laser_point", side = self.side_name()),
+            ChartEdit::AddLaserPoint {
+                side: self.side_index(),
+                section: self.section.tick(),
+                point: LaserTool::gsp(ry, v),
+            },
+        );
+    }
+
+    /// Joins the edited section to the next one when its last point is under `pos`,
+    /// otherwise splits it at the segment under `tick`.
+    fn split_or_join(
+        &self,
+        screen: &ScreenState,
+        tick: u32,
+        pos: Pos2,
+        actions: &mut ActionStack<Chart>,
+    ) {
+        let side = self.side_index();
+        let section = self.section.tick();
+
+        if self.point_at(screen, pos).map(|(index, _)| index) == Some(self.section.1.len() - 1) {
+            actions.new_action(
+                i18n::fl!("join_laser", side = self.side_name()),
+                ChartEdit::JoinLaserSections { side, section },
+            );
+            return;
+        }
+
+        let ry = tick.saturating_sub(section);
+        if let Some(segment) = self
+            .section
+            .segments()
+            .find(|s| s[0].ry <= ry && ry < s[1].ry)
+        {
+            actions.new_action(
+                i18n::fl!("split_laser", side = self.side_name()),
+                ChartEdit::SplitLaserSection {
+                    side,
+                    section,
+                    ry: segment[0].ry,
+                },
+            );
+        }
     }
 }
 
@@ -115,12 +327,12 @@ impl CursorObject for LaserTool {
                 //hit test existing lasers
                 //if a laser exists enter edit mode for that laser
                 //if no lasers exist create new laser
-                let side_index: usize = if self.right { 1 } else { 0 };
-                if let Some(section_index) = self.hit_test(chart, tick) {
-                    self.section = chart.note.laser[side_index][section_index].clone();
+                if let Some(section) = self.hit_test(chart, tick) {
+                    self.section = section.clone();
                     self.mode = LaserEditMode::Edit(LaserEditState {
-                        section_index,
+                        section_tick: section.tick(),
                         curving_index: None,
+                        moving: None,
                     });
                 } else {
                     self.section.0 = tick;
@@ -141,22 +353,7 @@ impl CursorObject for LaserTool {
                     self.mode = LaserEditMode::None;
                     self.section.1.pop();
                     let v = std::mem::replace(&mut self.section, LaserSection(0, Vec::new(), 1));
-                    let v = std::rc::Rc::new(v); //Can't capture by clone so use RC
-                    let i = if self.right { 1 } else { 0 };
-                    let new_action = actions.new_action();
-                    new_action.description = i18n::fl!(
-                        "add_laser",
-                        side = if self.right {
-                            i18n::fl!("right")
-                        } else {
-                            i18n::fl!("left")
-                        }
-                    );
-                    new_action.action = Box::new(move |edit_chart| {
-                        edit_chart.note.laser[i].push(v.as_ref().clone());
-                        edit_chart.note.laser[i].sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-                        Ok(())
-                    });
+                    self.placed = Some(v);
 
                     return;
                 }
@@ -167,7 +364,19 @@ impl CursorObject for LaserTool {
                 ));
             }
             LaserEditMode::Edit(edit_state) => {
-                if self.hit_test(chart, tick) == Some(edit_state.section_index) {
+                if self.hit_test(chart, tick).map(LaserSection::tick)
+                    == Some(edit_state.section_tick)
+                {
+                    if !modifiers.alt {
+                        if let Some((index, slam_end)) = self.point_at(&screen, pos) {
+                            self.mode = LaserEditMode::Edit(LaserEditState {
+                                moving: Some(self.start_point_drag(chart, index, slam_end)),
+                                ..edit_state
+                            });
+                            return;
+                        }
+                    }
+
                     for (i, points) in self.section.segments().enumerate() {
                         if let Some(control_point) = screen.get_control_point_pos_section(
                             points,
@@ -177,13 +386,29 @@ impl CursorObject for LaserTool {
                         ) {
                             if control_point.distance(pos) < 5.0 {
                                 self.mode = LaserEditMode::Edit(LaserEditState {
-                                    section_index: edit_state.section_index,
+                                    section_tick: edit_state.section_tick,
                                     curving_index: Some(i),
+                                    moving: None,
                                 })
                             }
                         }
                     }
-                //TODO: Subdivide and stuff
+
+                    let grabbed_curve = matches!(
+                        self.mode,
+                        LaserEditMode::Edit(LaserEditState {
+                            curving_index: Some(_),
+                            ..
+                        })
+                    );
+                    if grabbed_curve {
+                        return;
+                    }
+                    if modifiers.alt {
+                        self.split_or_join(&screen, tick, pos, actions);
+                    } else {
+                        self.insert_point(tick, actions);
+                    }
                 } else {
                     self.mode = LaserEditMode::None;
                     self.section = LaserSection(tick, Vec::new(), 1)
@@ -197,11 +422,43 @@ impl CursorObject for LaserTool {
         _tick: u32,
         _tick_f: f64,
         _lane: f32,
-        _chart: &Chart,
+        chart: &Chart,
         actions: &mut ActionStack<Chart>,
         _pos: Pos2,
     ) {
         if let LaserEditMode::Edit(edit_state) = self.mode {
+            if edit_state.moving.is_some() {
+                let original = chart.note.laser[self.side_index()]
+                    .iter()
+                    .find(|s| s.tick() == edit_state.section_tick);
+                let moved = original.map_or(false, |original| {
+                    original.tick() != self.section.tick()
+                        || original.1.iter().zip(&self.section.1).any(|(a, b)| {
+                            a.ry != b.ry || a.v.to_bits() != b.v.to_bits() || a.vf != b.vf
+                        })
+                });
+                if moved {
+                    actions.new_action(
+                        i18n::fl!("move_laser_point", side = self.side_name()),
+                        ChartEdit::Batch(vec![
+                            ChartEdit::RemoveLaserSection {
+                                side: self.side_index(),
+                                tick: edit_state.section_tick,
+                            },
+                            ChartEdit::AddLaserSection {
+                                side: self.side_index(),
+                                section: self.section.clone(),
+                            },
+                        ]),
+                    );
+                }
+                self.mode = LaserEditMode::Edit(LaserEditState {
+                    section_tick: self.section.tick(),
+                    curving_index: None,
+                    moving: None,
+                });
+                return;
+            }
             if let Some(curving_index) = edit_state.curving_index {
                 let right = self.right;
                 let laser_text = if right {
@@ -209,53 +466,91 @@ impl CursorObject for LaserTool {
                 } else {
                     i18n::fl!("left")
                 };
-                let section_index = edit_state.section_index;
-                let laser_i = if right { 1 } else { 0 };
                 let updated_point = self.section.1[curving_index];
 
-                let new_action = actions.new_action();
-                new_action.description = i18n::fl!("adjust_laser_curve", side = laser_text);
-                new_action.action = Box::new(move |c| {
-                    c.note.laser[laser_i][section_index].1[curving_index] = updated_point;
-                    Ok(())
-                });
+                actions.new_merging_action(
+                    &format!(
+                        "laser_curve:{}:{}:{}",
+                        self.side_index(),
+                        edit_state.section_tick,
+                        updated_point.ry
+                    ),
+                    i18n::fl!("adjust_laser_curve", side = laser_text),
+                    ChartEdit::SetLaserPoint {
+                        side: self.side_index(),
+                        section: edit_state.section_tick,
+                        ry: updated_point.ry,
+                        point: updated_point,
+                    },
+                );
             }
             self.mode = LaserEditMode::Edit(LaserEditState {
-                section_index: edit_state.section_index,
+                section_tick: edit_state.section_tick,
                 curving_index: None,
+                moving: None,
             })
         }
     }
 
     fn middle_click(
         &mut self,
-        _screen: ScreenState,
+        screen: ScreenState,
         tick: u32,
         _tick_f: f64,
         _lane: f32,
         chart: &Chart,
         actions: &mut ActionStack<Chart>,
-        _pos: Pos2,
+        pos: Pos2,
     ) {
-        if let Some(index) = self.hit_test(chart, tick) {
-            let laser_i = if self.right { 1 } else { 0 };
-            let new_action = actions.new_action();
-            new_action.description = i18n::fl!(
-                "remove_laser",
-                side = if self.right {
-                    i18n::fl!("right")
-                } else {
-                    i18n::fl!("left")
+        if let LaserEditMode::Edit(edit_state) = self.mode {
+            if let Some((index, _)) = self.point_at(&screen, pos) {
+                // A section needs two points, so removing one of the last two removes it
+                if self.section.1.len() <= 2 {
+                    actions.new_action(
+                        i18n::fl!("remove_laser", side = self.side_name()),
+                        ChartEdit::RemoveLaserSection {
+                            side: self.side_index(),
+                            tick: edit_state.section_tick,
+                        },
+                    );
+                    self.mode = LaserEditMode::None;
+                    self.section = LaserSection(tick, Vec::new(), 1);
+                    return;
+                }
+
+                let ry = self.section.1[index].ry;
+                actions.new_action(
+                    i18n::fl!("remove_laser_point", side = self.side_name()),
+                    ChartEdit::RemoveLaserPoint {
+                        side: self.side_index(),
+                        section: edit_state.section_tick,
+                        ry,
+                    },
+                );
+                // Removing the first point moves the start of the section to the next one
+                if index == 0 {
+                    self.mode = LaserEditMode::Edit(LaserEditState {
+                        section_tick: edit_state.section_tick + self.section.1[1].ry,
+                        curving_index: None,
+                        moving: None,
+                    });
                 }
+                return;
+            }
+        }
+
+        if let Some(section) = self.hit_test(chart, tick) {
+            actions.new_action(
+                i18n::fl!("remove_laser", side = self.side_name()),
+                ChartEdit::RemoveLaserSection {
+                    side: self.side_index(),
+                    tick: section.tick(),
+                },
             );
-            new_action.action = Box::new(move |chart: &mut Chart| {
-                chart.note.laser[laser_i].remove(index);
-                Ok(())
-            });
         }
     }
 
-    fn update(&mut self, tick: u32, tick_f: f64, lane: f32, _pos: Pos2, _chart: &Chart) {
+    fn update(&mut self, tick: u32, tick_f: f64, lane: f32, _pos: Pos2, chart: &Chart) {
         match self.mode {
             LaserEditMode::New => {
                 let ry = self.calc_ry(tick);
@@ -277,6 +572,26 @@ impl CursorObject for LaserTool {
             }
             LaserEditMode::None => {}
             LaserEditMode::Edit(edit_state) => {
+                if let Some(drag) = edit_state.moving {
+                    if let Some(original) = chart.note.laser[self.side_index()]
+                        .iter()
+                        .find(|s| s.tick() == edit_state.section_tick)
+                    {
+                        self.section = LaserTool::dragged_section(original, drag, tick, lane);
+                    }
+                    return;
+                }
+
+                // Pick up changes to the section once they have been applied to the chart,
+                // until then the tool keeps showing its own copy
+                if edit_state.curving_index.is_none() {
+                    let sections = &chart.note.laser[self.side_index()];
+                    if let Ok(index) =
+                        sections.binary_search_by_key(&edit_state.section_tick, |s| s.tick())
+                    {
+                        self.section = sections[index].clone();
+                    }
+                }
                 for gp in &mut self.section.1 {
                     if gp.a.is_none() {
                         gp.a = Some(0.5);
@@ -334,6 +649,16 @@ impl CursorObject for LaserTool {
 
             //Draw curve control points
             if let LaserEditMode::Edit(edit_state) = self.mode {
+                for (i, point) in self.section.1.iter().enumerate() {
+                    let color = match edit_state.moving {
+                        Some(drag) if drag.index == i => Color32::YELLOW,
+                        _ => Color32::WHITE,
+                    };
+                    for pos in self.point_positions(&state.screen, point) {
+                        painter.circle_stroke(pos, 4.0, Stroke::new(1.5, color));
+                    }
+                }
+
                 for (i, start_end) in self.section.1.windows(2).enumerate() {
                     let color = if edit_state.curving_index == Some(i) {
                         Rgba::from_rgba_premultiplied(0.0, 1.0, 0.0, 1.0)
@@ -354,4 +679,61 @@ impl CursorObject for LaserTool {
         }
         Ok(())
     }
+
+    fn secondary_click(
+        &mut self,
+        _screen: ScreenState,
+        tick: u32,
+        _tick_f: f64,
+        _lane: f32,
+        _chart: &Chart,
+        _actions: &mut ActionStack<Chart>,
+        pos: Pos2,
+    ) {
+        self.context = (tick, pos);
+    }
+
+    fn context_menu(&mut self, state: &mut MainState, ui: &mut Ui) {
+        if !matches!(self.mode, LaserEditMode::Edit(_)) {
+            return;
+        }
+
+        let (tick, pos) = self.context;
+        ui.separator();
+        if ui
+            .button(i18n::fl!("add_laser_point", side = self.side_name()))
+            .clicked()
+        {
+            self.insert_point(tick, &mut state.actions);
+            ui.close_menu();
+        }
+
+        let joins = self.point_at(&state.screen, pos).map(|(index, _)| index)
+            == Some(self.section.1.len() - 1);
+        let label = if joins {
+            i18n::fl!("join_laser", side = self.side_name())
+        } else {
+            i18n::fl!("split_laser", side = self.side_name())
+        };
+        if ui.button(label).clicked() {
+            self.split_or_join(&state.screen, tick, pos, &mut state.actions);
+            ui.close_menu();
+        }
+    }
+
+    fn draw_ui(&mut self, state: &mut MainState, _ctx: &Context) {
+        if let Some(section) = self.placed.take() {
+            match overlap::place_laser_section(
+                &state.chart,
+                self.side_index(),
+                section,
+                state.overlap_policy,
+            ) {
+                Some(edit) => state
+                    .actions
+                    .new_action(i18n::fl!("add_laser", side = self.side_name()), edit),
+                None => state.notifications.info(i18n::fl!("overlap_rejected")),
+            }
+        }
+    }
 }
@@ -5,17 +5,21 @@ use crate::{
 };
 use anyhow::Result;
 use eframe::egui::Pos2;
-use eframe::egui::{Context, Painter};
+use eframe::egui::{Context, Painter, Ui};
 use kson::Chart;
 
 mod bpm_ts;
 mod buttons;
 mod camera;
+mod eraser;
 mod laser;
+mod select;
 pub use bpm_ts::*;
 pub use buttons::*;
 pub use camera::*;
+pub use eraser::*;
 pub use laser::*;
+pub use select::*;
 
 pub trait CursorObject {
     fn primary_click(
@@ -83,4 +87,7 @@ pub trait CursorObject {
     fn update(&mut self, tick: u32, tick_f: f64, lane: f32, pos: Pos2, chart: &Chart);
     fn draw(&self, state: &MainState, painter: &Painter) -> Result<()>;
     fn draw_ui(&mut self, _state: &mut MainState, _ctx: &Context) {}
+    /// Adds the tool's own entries below the common ones in the context menu opened by the
+    /// last `secondary_click`.
+    fn context_menu(&mut self, _state: &mut MainState, _ui: &mut Ui) {}
 }
@@ -0,0 +1,199 @@
+use crate::selection::{self, Selection, SelectionArea};
+use crate::tools::{button_lane, CursorObject};
+use crate::transform::{self, Offset};
+use crate::{
+    action_stack::ActionStack,
+    chart_editor::{MainState, ScreenState},
+    rect_xy_wh, Modifiers,
+};
+use anyhow::Result;
+use eframe::egui::{Color32, Context, Painter, Pos2, Stroke};
+use kson::{Chart, Interval};
+
+enum DragState {
+    None,
+    /// Dragging a selection rectangle from the given tick and lane
+    Area(u32, f32),
+    /// Dragging the selected objects from the given snapped tick and lane
+    Move(u32, f32),
+}
+
+/// Selects chart objects by clicking them or dragging a rectangle around them, dragging
+/// a selected object moves the whole selection.
+pub struct SelectTool {
+    drag: DragState,
+    /// Unsnapped and snapped tick under the cursor, and its lane
+    cursor: (u32, u32, f32),
+    /// Add to the current selection instead of replacing it
+    additive: bool,
+    /// Copy of the editor selection from the last ui update
+    selection: Selection,
+    /// Area selected since the last ui update, applied to the selection in `draw_ui`
+    pending_area: Option<(SelectionArea, bool)>,
+    /// Tick and lane clicked since the last ui update, the nearest object there is
+    /// selected in `draw_ui`
+    pending_click: Option<(u32, f32)>,
+    pending_move: Option<Offset>,
+}
+
+impl SelectTool {
+    pub fn new() -> Self {
+        SelectTool {
+            drag: DragState::None,
+            cursor: (0, 0, 0.0),
+            additive: false,
+            selection: Selection::default(),
+            pending_area: None,
+            pending_click: None,
+            pending_move: None,
+        }
+    }
+
+    fn move_offset(&self, from_tick: u32, from_lane: f32) -> Offset {
+        let (_, tick, lane) = self.cursor;
+        let lanes = |fx| button_lane(fx, lane) as i32 - button_lane(fx, from_lane) as i32;
+        Offset {
+            ticks: tick as i64 - from_tick as i64,
+            bt_lanes: lanes(false),
+            fx_lanes: lanes(true),
+        }
+    }
+}
+
+impl CursorObject for SelectTool {
+    fn primary_click(
+        &mut self,
+        _screen: ScreenState,
+        _tick: u32,
+        tick_f: f64,
+        lane: f32,
+        _chart: &Chart,
+        _actions: &mut ActionStack<Chart>,
+        _pos: Pos2,
+    ) {
+        self.pending_click = Some((tick_f as u32, lane));
+    }
+
+    fn drag_start(
+        &mut self,
+        _screen: ScreenState,
+        tick: u32,
+        tick_f: f64,
+        lane: f32,
+        chart: &Chart,
+        _actions: &mut ActionStack<Chart>,
+        _pos: Pos2,
+        modifiers: &Modifiers,
+    ) {
+        self.cursor = (tick_f as u32, tick, lane);
+        self.additive = modifiers.shift;
+
+        let grabbed_selection = !modifiers.shift
+            && SelectionArea::around(tick_f as u32, lane, chart.beat.resolution)
+                .objects(chart)
+                .iter()
+                .any(|o| self.selection.contains(o));
+
+        self.drag = if grabbed_selection {
+            DragState::Move(tick, lane)
+        } else {
+            DragState::Area(tick_f as u32, lane)
+        };
+    }
+
+    fn drag_end(
+        &mut self,
+        _screen: ScreenState,
+        tick: u32,
+        tick_f: f64,
+        lane: f32,
+        _chart: &Chart,
+        _actions: &mut ActionStack<Chart>,
+        _pos: Pos2,
+    ) {
+        self.cursor = (tick_f as u32, tick, lane);
+        match std::mem::replace(&mut self.drag, DragState::None) {
+            DragState::Area(start_tick, start_lane) => {
+                let area = SelectionArea::new((start_tick, start_lane), (tick_f as u32, lane));
+                self.pending_area = Some((area, self.additive));
+            }
+            DragState::Move(start_tick, start_lane) => {
+                self.pending_move = Some(self.move_offset(start_tick, start_lane));
+            }
+            DragState::None => {}
+        }
+    }
+
+    fn update(&mut self, tick: u32, tick_f: f64, lane: f32, _pos: Pos2, _chart: &Chart) {
+        self.cursor = (tick_f as u32, tick, lane);
+    }
+
+    fn draw(&self, state: &MainState, painter: &Painter) -> Result<()> {
+        match self.drag {
+            DragState::Area(start_tick, start_lane) => {
+                let area =
+                    SelectionArea::new((start_tick, start_lane), (self.cursor.0, self.cursor.2));
+                let screen = &state.screen;
+                let lane_width = screen.lane_width();
+                let x_offset = screen.track_width / 2.0 + area.lanes.0 * lane_width;
+                let w = (area.lanes.1 - area.lanes.0) * lane_width;
+
+                // Split the rectangle where it wraps over to the next column
+                for (x, y, h, _) in screen.interval_to_ranges(&Interval {
+                    y: area.start,
+                    l: area.end - area.start,
+                }) {
+                    let rect = rect_xy_wh([x + x_offset, y, w, h]);
+                    painter.rect(
+                        rect,
+                        0.0,
+                        Color32::from_rgba_unmultiplied(80, 200, 255, 40),
+                        Stroke::new(1.0, Color32::from_rgb(80, 200, 255)),
+                    );
+                }
+            }
+            DragState::Move(start_tick, start_lane) => {
+                let offset = self.move_offset(start_tick, start_lane);
+                if let Some((_, moved)) =
+                    transform::move_objects(&state.chart, &self.selection, offset)
+                {
+                    for (old, new) in moved {
+                        selection::draw_object(state, painter, old, new, Color32::WHITE);
+                    }
+                }
+            }
+            DragState::None => {}
+        }
+
+        Ok(())
+    }
+
+    fn draw_ui(&mut self, state: &mut MainState, _ctx: &Context) {
+        if let Some((area, additive)) = self.pending_area.take() {
+            if !additive {
+                state.selection.clear();
+            }
+            let locks = state.layer_locks;
+            state.selection.extend(
+                area.objects(&state.chart)
+                    .into_iter()
+                    .filter(|o| !locks.is_locked(o)),
+            );
+            state.actions.seal();
+        }
+        if let Some((tick, lane)) = self.pending_click.take() {
+            state.selection.clear();
+            state.selection.extend(selection::nearest(
+                &state.chart,
+                tick,
+                lane,
+                &state.layer_locks,
+            ));
+            state.actions.seal();
+        }
+        if let Some(offset) = self.pending_move.take() {
+            state.move_selection(offset, None);
+        }
+        self.selection = state.selection.clone();
+    }
+}
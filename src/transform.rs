@@ -0,0 +1,1159 @@
+use std::convert::TryFrom;
+
+use anyhow::{anyhow, bail, Result};
+use kson::{Chart, Interval, LaserSection, TimeSignature};
+use serde::{Deserialize, Serialize};
+
+use crate::chart_edit::{camera_graph_mut, ChartEdit};
+use crate::selection::{ObjectId, Selection};
+use crate::snap::SnapDivision;
+use crate::tools::{camera_graph, CameraPaths};
+use crate::utils::Overlaps;
+
+/// How far to move objects, lanes are counted separately for BT and FX since they have
+/// a different number of lanes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Offset {
+    pub ticks: i64,
+    pub bt_lanes: i32,
+    pub fx_lanes: i32,
+}
+
+fn shift_lane(lane: usize, by: i32, count: usize) -> Option<usize> {
+    let lane = lane as i64 + by as i64;
+    if (0..count as i64).contains(&lane) {
+        Some(lane as usize)
+    } else {
+        None
+    }
+}
+
+/// The selected objects that can be moved: intervals with their lane, whole laser sections
+/// and BPM changes other than the first.
+struct Movable<'a> {
+    intervals: Vec<(bool, usize, Interval)>,
+    sections: Vec<(usize, &'a LaserSection)>,
+    bpms: Vec<(u32, f64)>,
+}
+
+impl<'a> Movable<'a> {
+    fn new(chart: &'a Chart, selection: &Selection) -> Self {
+        let mut movable = Movable {
+            intervals: Vec::new(),
+            sections: Vec::new(),
+            bpms: Vec::new(),
+        };
+
+        for object in selection.iter() {
+            match *object {
+                ObjectId::Interval { fx, lane, y } => {
+                    let notes = if fx {
+                        &chart.note.fx[lane]
+                    } else {
+                        &chart.note.bt[lane]
+                    };
+                    if let Ok(index) = notes.binary_search_by_key(&y, |n| n.y) {
+                        movable.intervals.push((fx, lane, notes[index]));
+                    }
+                }
+                ObjectId::LaserSection { side, tick } => {
+                    let sections = &chart.note.laser[side];
+                    if let Ok(index) = sections.binary_search_by_key(&tick, |s| s.0) {
+                        movable.sections.push((side, &sections[index]));
+                    }
+                }
+                ObjectId::Bpm { tick } if tick > 0 => {
+                    if let Ok(index) = chart.beat.bpm.binary_search_by_key(&tick, |b| b.0) {
+                        movable.bpms.push(chart.beat.bpm[index]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        movable
+    }
+
+    fn start_tick(&self) -> Option<u32> {
+        self.intervals
+            .iter()
+            .map(|(_, _, n)| n.y)
+            .chain(self.sections.iter().map(|(_, s)| s.0))
+            .chain(self.bpms.iter().map(|b| b.0))
+            .min()
+    }
+}
+
+/// Tick of the earliest selected object that can be moved.
+pub fn start_tick(chart: &Chart, selection: &Selection) -> Option<u32> {
+    Movable::new(chart, selection).start_tick()
+}
+
+/// The edit that moves the selected objects, along with the old and new id of every moved
+/// object. `None` if nothing would move.
+///
+/// BT and FX notes move in time and across their lanes, laser sections and BPM changes
+/// only move in time. Laser points selected without their section, time signatures and the
+/// BPM at the start of the chart stay in place. A lane move that would take any note
+/// outside its lanes is dropped, a time move is cut short at the start of the chart.
+/// Nothing moves if a note or laser section would overlap one that isn't moved.
+pub fn move_objects(
+    chart: &Chart,
+    selection: &Selection,
+    offset: Offset,
+) -> Option<(ChartEdit, Vec<(ObjectId, ObjectId)>)> {
+    let movable = Movable::new(chart, selection);
+    let first_tick = movable.start_tick()?;
+    let Movable {
+        intervals,
+        sections,
+        bpms,
+    } = movable;
+    let ticks = offset.ticks.max(-(first_tick as i64));
+    let bt_lanes = if intervals.iter().all(|&(fx, lane, _)| {
+        fx || shift_lane(lane, offset.bt_lanes, chart.note.bt.len()).is_some()
+    }) {
+        offset.bt_lanes
+    } else {
+        0
+    };
+    let fx_lanes = if intervals.iter().all(|&(fx, lane, _)| {
+        !fx || shift_lane(lane, offset.fx_lanes, chart.note.fx.len()).is_some()
+    }) {
+        offset.fx_lanes
+    } else {
+        0
+    };
+
+    let moved_intervals = intervals.iter().any(|&(fx, ..)| {
+        let lanes = if fx { fx_lanes } else { bt_lanes };
+        lanes != 0
+    });
+    if ticks == 0 && !moved_intervals {
+        return None;
+    }
+
+    let shift = |tick: u32| (tick as i64 + ticks) as u32;
+    // Everything is removed before it is added back so moved objects can't collide
+    // with each other
+    let mut removals = Vec::new();
+    let mut additions = Vec::new();
+    let mut moved = Vec::new();
+
+    for &(fx, lane, note) in &intervals {
+        let (lanes, count) = if fx {
+            (fx_lanes, chart.note.fx.len())
+        } else {
+            (bt_lanes, chart.note.bt.len())
+        };
+        let new_lane = shift_lane(lane, lanes, count).unwrap_or(lane);
+        let interval = Interval {
+            y: shift(note.y),
+            l: note.l,
+        };
+        let lane_notes = if fx {
+            &chart.note.fx[new_lane]
+        } else {
+            &chart.note.bt[new_lane]
+        };
+        let collides = lane_notes
+            .iter()
+            .filter(|n| {
+                !intervals
+                    .iter()
+                    .any(|&(f, l, m)| f == fx && l == new_lane && m.y == n.y)
+            })
+            .any(|n| n.overlaps(&interval));
+        if collides {
+            return None;
+        }
+
+        removals.push(ChartEdit::RemoveInterval {
+            fx,
+            lane,
+            y: note.y,
+        });
+        additions.push(ChartEdit::AddInterval {
+            fx,
+            lane: new_lane,
+            interval,
+        });
+        moved.push((
+            ObjectId::Interval {
+                fx,
+                lane,
+                y: note.y,
+            },
+            ObjectId::Interval {
+                fx,
+                lane: new_lane,
+                y: interval.y,
+            },
+        ));
+    }
+
+    if ticks != 0 {
+        for &(side, section) in &sections {
+            let mut new_section = section.clone();
+            new_section.0 = shift(section.0);
+            let collides = chart.note.laser[side]
+                .iter()
+                .filter(|s| !sections.iter().any(|&(o, m)| o == side && m.0 == s.0))
+                .any(|s| s.overlaps(&new_section));
+            if collides {
+                return None;
+            }
+
+            removals.push(ChartEdit::RemoveLaserSection {
+                side,
+                tick: section.0,
+            });
+            moved.push((
+                ObjectId::LaserSection {
+                    side,
+                    tick: section.0,
+                },
+                ObjectId::LaserSection {
+                    side,
+                    tick: new_section.0,
+                },
+            ));
+            moved.extend(section.1.iter().map(|p| {
+                (
+                    ObjectId::LaserPoint {
+                        side,
+                        section: section.0,
+                        ry: p.ry,
+                    },
+                    ObjectId::LaserPoint {
+                        side,
+                        section: new_section.0,
+                        ry: p.ry,
+                    },
+                )
+            }));
+            additions.push(ChartEdit::AddLaserSection {
+                side,
+                section: new_section,
+            });
+        }
+
+        for (tick, bpm) in bpms {
+            removals.push(ChartEdit::RemoveBpm { tick });
+            additions.push(ChartEdit::AddBpm {
+                tick: shift(tick),
+                bpm,
+            });
+            moved.push((ObjectId::Bpm { tick }, ObjectId::Bpm { tick: shift(tick) }));
+        }
+    }
+
+    removals.extend(additions);
+    Some((ChartEdit::Batch(removals), moved))
+}
+
+/// An operation applied to every object in a tick range, or in the whole chart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Transform {
+    /// Swaps BT A/D and B/C, FX L/R and the laser sides, laser values are inverted.
+    Mirror,
+    /// Moves objects by a number of ticks.
+    Shift(i64),
+    /// Stretches the range by `numerator / denominator` from its start, everything after
+    /// the range moves with its end.
+    Scale { numerator: u32, denominator: u32 },
+}
+
+/// Decides where the ticks of an object end up, based on the tick the object starts at.
+trait TickMap {
+    /// New position of `tick` in an object starting at `anchor`.
+    fn tick(&self, anchor: u32, tick: u32) -> Result<u32>;
+
+    /// Whether an object starting at `anchor` moves to the opposite lane.
+    fn mirrors(&self, _anchor: u32) -> bool {
+        false
+    }
+
+    /// Whether time signature changes move with the ticks, otherwise they stay on
+    /// their measure.
+    fn moves_measures(&self) -> bool {
+        true
+    }
+}
+
+/// Objects belong to the range they start in so long notes and laser sections are always
+/// transformed as a whole.
+struct Mapping {
+    transform: Transform,
+    start: u32,
+    end: u32,
+}
+
+impl Mapping {
+    fn scaled(&self, ticks: u32) -> i64 {
+        match self.transform {
+            Transform::Scale {
+                numerator,
+                denominator,
+            } => {
+                let (n, d) = (numerator as u64, denominator.max(1) as u64);
+                ((ticks as u64 * n + d / 2) / d) as i64
+            }
+            _ => ticks as i64,
+        }
+    }
+}
+
+impl TickMap for Mapping {
+    fn mirrors(&self, anchor: u32) -> bool {
+        self.transform == Transform::Mirror && (self.start..=self.end).contains(&anchor)
+    }
+
+    fn tick(&self, anchor: u32, tick: u32) -> Result<u32> {
+        let new_tick = if anchor < self.start {
+            tick as i64
+        } else if anchor <= self.end {
+            match self.transform {
+                Transform::Mirror => tick as i64,
+                Transform::Shift(ticks) => tick as i64 + ticks,
+                Transform::Scale { .. } => self.start as i64 + self.scaled(tick - self.start),
+            }
+        } else {
+            let length = self.end - self.start;
+            tick as i64 + self.scaled(length) - length as i64
+        };
+
+        u32::try_from(new_tick).map_err(|_| anyhow!("Objects would be moved outside of the chart"))
+    }
+}
+
+fn transform_intervals(lanes: &mut [Vec<Interval>], mapping: &impl TickMap) -> Result<()> {
+    let count = lanes.len();
+    let mut transformed = vec![Vec::new(); count];
+
+    for (lane, notes) in lanes.iter().enumerate() {
+        for note in notes {
+            let new_lane = if mapping.mirrors(note.y) {
+                count - 1 - lane
+            } else {
+                lane
+            };
+            let y = mapping.tick(note.y, note.y)?;
+            let end = mapping.tick(note.y, note.y + note.l)?;
+            transformed[new_lane].push(Interval { y, l: end - y });
+        }
+    }
+
+    for (lane, mut notes) in transformed.into_iter().enumerate() {
+        notes.sort_by_key(|n: &Interval| n.y);
+        if notes.windows(2).any(|n| n[0].overlaps(&n[1])) {
+            bail!("Notes in lane {} would overlap", lane);
+        }
+        lanes[lane] = notes;
+    }
+
+    Ok(())
+}
+
+fn transform_lasers(chart: &mut Chart, mapping: &impl TickMap) -> Result<()> {
+    let mut transformed = vec![Vec::new(); chart.note.laser.len()];
+
+    for (side, sections) in chart.note.laser.iter().enumerate() {
+        for section in sections {
+            let mirrored = mapping.mirrors(section.0);
+            let mut new_section = section.clone();
+            new_section.0 = mapping.tick(section.0, section.0)?;
+            for point in &mut new_section.1 {
+                point.ry = mapping.tick(section.0, section.0 + point.ry)? - new_section.0;
+                if mirrored {
+                    point.v = 1.0 - point.v;
+                    point.vf = point.vf.map(|v| 1.0 - v);
+                }
+            }
+            let new_side = if mirrored { 1 - side } else { side };
+            transformed[new_side].push(new_section);
+        }
+    }
+
+    for (side, mut sections) in transformed.into_iter().enumerate() {
+        sections.sort_by_key(|s: &LaserSection| s.0);
+        if sections.windows(2).any(|s| s[0].overlaps(&s[1])) {
+            bail!("Laser sections would overlap");
+        }
+        chart.note.laser[side] = sections;
+    }
+
+    Ok(())
+}
+
+/// Turns time signature changes at the given ticks back into measure indexed changes,
+/// fails if one of them would not start at a measure.
+fn time_signatures_at(
+    changes: &[(u32, TimeSignature)],
+    resolution: u32,
+) -> Result<Vec<(u32, TimeSignature)>> {
+    let mut time_sigs = Vec::new();
+    // Measure, tick and signature of the previous change
+    let mut previous = (0, 0, TimeSignature(4, 4));
+
+    for &(tick, time_sig) in changes {
+        let (measure, start, TimeSignature(n, d)) = previous;
+        let measure_ticks = resolution * 4 * n / d.max(1);
+        let ticks = tick - start;
+        if measure_ticks == 0 || ticks % measure_ticks != 0 {
+            bail!("A time signature change would not start at a measure");
+        }
+        let measure = measure + ticks / measure_ticks;
+        time_sigs.push((measure, time_sig));
+        previous = (measure, tick, time_sig);
+    }
+
+    Ok(time_sigs)
+}
+
+/// Sorts changes by tick, fails if two of them end up at the same tick.
+fn sorted_changes<T>(mut changes: Vec<(u32, T)>, what: &str) -> Result<Vec<(u32, T)>> {
+    changes.sort_by_key(|c| c.0);
+    if let Some(c) = changes.windows(2).find(|c| c[0].0 == c[1].0) {
+        bail!("Two {} would end up at {}", what, c[0].0);
+    }
+    Ok(changes)
+}
+
+/// Applies `transform` to every object starting in `range`, or the whole chart.
+///
+/// The first BPM and time signature change stay at the start of the chart. Fails without
+/// changing anything if objects would overlap or leave the chart, or if a time signature
+/// change would no longer start a measure.
+pub fn transform_chart(
+    chart: &mut Chart,
+    range: Option<(u32, u32)>,
+    transform: Transform,
+) -> Result<()> {
+    if let Transform::Scale { denominator: 0, .. } = transform {
+        bail!("Can't scale by a ratio with a denominator of 0");
+    }
+    let (start, end) = range.unwrap_or((0, u32::MAX));
+    let mapping = Mapping {
+        transform,
+        start,
+        end,
+    };
+    let resolution = chart.beat.resolution;
+    apply_mapping(chart, &mapping, resolution)
+}
+
+/// Moves every object of the chart according to `mapping` and sets its resolution.
+fn apply_mapping(chart: &mut Chart, mapping: &impl TickMap, resolution: u32) -> Result<()> {
+    let fixed_at_start = |tick: u32| {
+        if tick == 0 {
+            Ok(0)
+        } else {
+            mapping.tick(tick, tick)
+        }
+    };
+
+    let mut result = chart.clone();
+    result.beat.resolution = resolution;
+    transform_intervals(&mut result.note.bt, mapping)?;
+    transform_intervals(&mut result.note.fx, mapping)?;
+    transform_lasers(&mut result, mapping)?;
+
+    let mut bpm = Vec::new();
+    for &(tick, value) in &chart.beat.bpm {
+        bpm.push((fixed_at_start(tick)?, value));
+    }
+    result.beat.bpm = sorted_changes(bpm, "BPM changes")?;
+
+    if mapping.moves_measures() {
+        let mut time_sig = Vec::new();
+        for &(measure, value) in &chart.beat.time_sig {
+            time_sig.push((fixed_at_start(chart.measure_to_tick(measure))?, value));
+        }
+        let time_sig = sorted_changes(time_sig, "time signature changes")?;
+        result.beat.time_sig = time_signatures_at(&time_sig, resolution)?;
+    }
+
+    for &graph in CameraPaths::ALL.iter() {
+        let points = camera_graph_mut(&mut result, graph);
+        let mut moved = Vec::new();
+        for point in points.iter() {
+            let mut point = *point;
+            point.y = mapping.tick(point.y, point.y)?;
+            moved.push((point.y, point));
+        }
+        *points = sorted_changes(moved, "camera points")?
+            .into_iter()
+            .map(|(_, p)| p)
+            .collect();
+    }
+
+    *chart = result;
+    Ok(())
+}
+
+/// Objects with ticks that can't be represented exactly at another resolution.
+#[derive(Default)]
+pub struct ResolutionReport {
+    /// Notes, laser points, BPM changes and time signatures whose measures would not be a
+    /// whole number of ticks long.
+    pub objects: Vec<ObjectId>,
+    /// Camera points have no [`ObjectId`] so they are only counted.
+    pub camera_points: usize,
+}
+
+impl ResolutionReport {
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty() && self.camera_points == 0
+    }
+
+    /// Whether a time signature has measures that would not be a whole number of ticks
+    /// long. Those can't be rounded, so the resolution can't be changed at all.
+    pub fn has_inexact_time_signatures(&self) -> bool {
+        self.objects
+            .iter()
+            .any(|o| matches!(o, ObjectId::TimeSig { .. }))
+    }
+}
+
+/// Finds the objects that would have to be rounded to change the chart to `resolution`.
+pub fn resolution_report(chart: &Chart, resolution: u32) -> ResolutionReport {
+    let old_resolution = chart.beat.resolution.max(1) as u64;
+    let exact = |tick: u32| tick as u64 * resolution as u64 % old_resolution == 0;
+    let mut report = ResolutionReport::default();
+
+    for (fx, lanes) in [(false, &chart.note.bt[..]), (true, &chart.note.fx[..])].iter() {
+        for (lane, notes) in lanes.iter().enumerate() {
+            report.objects.extend(
+                notes
+                    .iter()
+                    .filter(|n| !exact(n.y) || !exact(n.y + n.l))
+                    .map(|n| ObjectId::Interval {
+                        fx: *fx,
+                        lane,
+                        y: n.y,
+                    }),
+            );
+        }
+    }
+
+    for (side, sections) in chart.note.laser.iter().enumerate() {
+        for section in sections {
+            report.objects.extend(
+                section
+                    .1
+                    .iter()
+                    .filter(|p| !exact(section.0 + p.ry))
+                    .map(|p| ObjectId::LaserPoint {
+                        side,
+                        section: section.0,
+                        ry: p.ry,
+                    }),
+            );
+        }
+    }
+
+    report.objects.extend(
+        chart
+            .beat
+            .bpm
+            .iter()
+            .filter(|b| !exact(b.0))
+            .map(|b| ObjectId::Bpm { tick: b.0 }),
+    );
+    report.objects.extend(
+        chart
+            .beat
+            .time_sig
+            .iter()
+            .filter(|&&(_, TimeSignature(n, d))| resolution * 4 * n % d.max(1) != 0)
+            .map(|t| ObjectId::TimeSig { measure: t.0 }),
+    );
+
+    report.camera_points = CameraPaths::ALL
+        .iter()
+        .map(|&graph| {
+            camera_graph(chart, graph)
+                .iter()
+                .filter(|p| !exact(p.y))
+                .count()
+        })
+        .sum();
+
+    report
+}
+
+/// Rescales every tick in the chart to `resolution`, ticks that don't fit the new
+/// resolution are rounded to the closest one. Fails if a time signature's measures would
+/// not be a whole number of ticks long, see [`ResolutionReport::has_inexact_time_signatures`].
+///
+/// Only the camera graphs in [`CameraPaths::ALL`] are rescaled, any other tick indexed
+/// camera data keeps its ticks. The same goes for every other transform.
+pub fn change_resolution(chart: &mut Chart, resolution: u32) -> Result<()> {
+    if resolution == 0 {
+        bail!("The resolution must be at least 1");
+    }
+    let mapping = Mapping {
+        transform: Transform::Scale {
+            numerator: resolution,
+            denominator: chart.beat.resolution.max(1),
+        },
+        start: 0,
+        end: u32::MAX,
+    };
+    apply_mapping(chart, &mapping, resolution)
+}
+
+/// Inserts `count` empty measures before `measure`, the new measures use the time
+/// signature and BPM in effect at the start of `measure`.
+///
+/// Long notes and laser sections that cross the start of `measure` are stretched over
+/// the new measures.
+pub fn insert_measures(chart: &mut Chart, measure: u32, count: u32) -> Result<()> {
+    if count == 0 {
+        bail!("No measures to insert");
+    }
+    let at = chart.measure_to_tick(measure);
+    let length = count * (chart.measure_to_tick(measure + 1) - at);
+    let shift = |tick: u32| if tick >= at { tick + length } else { tick };
+
+    for notes in chart.note.bt.iter_mut().chain(chart.note.fx.iter_mut()) {
+        for note in notes.iter_mut() {
+            let end = shift(note.y + note.l);
+            note.y = shift(note.y);
+            note.l = end - note.y;
+        }
+    }
+
+    for sections in chart.note.laser.iter_mut() {
+        for section in sections.iter_mut() {
+            let start = section.0;
+            section.0 = shift(start);
+            for point in section.1.iter_mut() {
+                point.ry = shift(start + point.ry) - section.0;
+            }
+        }
+    }
+
+    // Changes at the insertion point stay to apply to the new measures
+    for bpm in chart.beat.bpm.iter_mut().filter(|b| b.0 > at) {
+        bpm.0 += length;
+    }
+    for time_sig in chart.beat.time_sig.iter_mut().filter(|t| t.0 > measure) {
+        time_sig.0 += count;
+    }
+
+    for &graph in CameraPaths::ALL.iter() {
+        for point in camera_graph_mut(chart, graph).iter_mut() {
+            point.y = shift(point.y);
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes `count` measures starting at `measure` along with everything in them, later
+/// objects move back to fill the gap.
+///
+/// Long notes and laser sections that cross the deleted measures lose the part inside
+/// them. The BPM and time signature in effect after the deleted measures are kept.
+pub fn delete_measures(chart: &mut Chart, measure: u32, count: u32) -> Result<()> {
+    if count == 0 {
+        bail!("No measures to delete");
+    }
+    let start = chart.measure_to_tick(measure);
+    let end = chart.measure_to_tick(measure + count);
+    let length = end - start;
+    let deleted = |tick: u32| (start..end).contains(&tick);
+    let shift = |tick: u32| if tick >= end { tick - length } else { tick };
+
+    let bpm_after = chart.beat.bpm.iter().rev().find(|b| b.0 <= end).copied();
+    let time_sig_after = chart
+        .beat
+        .time_sig
+        .iter()
+        .rev()
+        .find(|t| t.0 <= measure + count)
+        .copied();
+
+    for notes in chart.note.bt.iter_mut().chain(chart.note.fx.iter_mut()) {
+        notes.retain(|n| !deleted(n.y));
+        for note in notes.iter_mut() {
+            let note_end = note.y + note.l;
+            // Holds ending inside the deleted measures end right before them so they
+            // don't touch what moves in after them
+            let note_end = if deleted(note_end) {
+                start.saturating_sub(1).max(note.y)
+            } else {
+                shift(note_end)
+            };
+            note.y = shift(note.y);
+            note.l = note_end - note.y;
+        }
+    }
+
+    for sections in chart.note.laser.iter_mut() {
+        let mut remaining = Vec::new();
+        for section in sections.drain(..) {
+            let points: Vec<_> = section
+                .1
+                .iter()
+                .map(|p| (section.0 + p.ry, *p))
+                .filter(|(tick, _)| !deleted(*tick))
+                .map(|(tick, p)| (shift(tick), p))
+                .collect();
+            if points.len() < 2 {
+                continue;
+            }
+
+            let new_start = points[0].0;
+            let mut new_section = section;
+            new_section.0 = new_start;
+            new_section.1 = points
+                .into_iter()
+                .map(|(tick, mut p)| {
+                    p.ry = tick - new_start;
+                    p
+                })
+                .collect();
+            remaining.push(new_section);
+        }
+        *sections = remaining;
+    }
+
+    let bpms = &mut chart.beat.bpm;
+    bpms.retain(|b| !deleted(b.0));
+    for bpm in bpms.iter_mut() {
+        bpm.0 = shift(bpm.0);
+    }
+    if let Some((_, bpm)) = bpm_after {
+        if let Err(index) = bpms.binary_search_by_key(&start, |b| b.0) {
+            let previous = index.checked_sub(1).map(|i| bpms[i].1);
+            if previous.map_or(true, |p| (p - bpm).abs() > f64::EPSILON) {
+                bpms.insert(index, (start, bpm));
+            }
+        }
+    }
+
+    let time_sigs = &mut chart.beat.time_sig;
+    time_sigs.retain(|t| !(measure..measure + count).contains(&t.0));
+    for time_sig in time_sigs.iter_mut().filter(|t| t.0 >= measure + count) {
+        time_sig.0 -= count;
+    }
+    if let Some((_, time_sig)) = time_sig_after {
+        if let Err(index) = time_sigs.binary_search_by_key(&measure, |t| t.0) {
+            let TimeSignature(n, d) = time_sig;
+            let previous = index.checked_sub(1).map(|i| time_sigs[i].1);
+            if !matches!(previous, Some(TimeSignature(pn, pd)) if pn == n && pd == d) {
+                time_sigs.insert(index, (measure, time_sig));
+            }
+        }
+    }
+
+    for &graph in CameraPaths::ALL.iter() {
+        let points = camera_graph_mut(chart, graph);
+        points.retain(|p| !deleted(p.y));
+        for point in points.iter_mut() {
+            point.y = shift(point.y);
+        }
+    }
+
+    Ok(())
+}
+
+/// Milliseconds from the start of the chart to `tick` with the given BPM changes.
+fn tick_to_ms(bpm: &[(u32, f64)], resolution: u32, tick: u32) -> f64 {
+    let mut ms = 0.0;
+    for (i, &(change_tick, change_bpm)) in bpm.iter().enumerate() {
+        if change_tick >= tick {
+            break;
+        }
+        let next_tick = bpm.get(i + 1).map_or(tick, |next| next.0.min(tick));
+        ms += (next_tick - change_tick) as f64 * 60000.0 / (change_bpm * resolution as f64);
+    }
+    ms
+}
+
+/// Tick, with a fraction, at `ms` from the start of the chart with the given BPM changes.
+fn ms_to_tick(bpm: &[(u32, f64)], resolution: u32, ms: f64) -> f64 {
+    let mut start_ms = 0.0;
+    for (i, &(change_tick, change_bpm)) in bpm.iter().enumerate() {
+        let ticks_per_ms = change_bpm * resolution as f64 / 60000.0;
+        if let Some(&(next_tick, _)) = bpm.get(i + 1) {
+            let next_ms = start_ms + (next_tick - change_tick) as f64 / ticks_per_ms;
+            if next_ms <= ms {
+                start_ms = next_ms;
+                continue;
+            }
+        }
+        return change_tick as f64 + (ms - start_ms) * ticks_per_ms;
+    }
+    0.0
+}
+
+/// Moves ticks after a BPM change so they keep their time in milliseconds.
+struct Retime {
+    tick: u32,
+    resolution: u32,
+    old_bpm: Vec<(u32, f64)>,
+    new_bpm: Vec<(u32, f64)>,
+}
+
+impl TickMap for Retime {
+    fn tick(&self, _anchor: u32, tick: u32) -> Result<u32> {
+        if tick <= self.tick {
+            return Ok(tick);
+        }
+        let ms = tick_to_ms(&self.old_bpm, self.resolution, tick);
+        let new_tick = ms_to_tick(&self.new_bpm, self.resolution, ms).round();
+        Ok(new_tick.max(self.tick as f64) as u32)
+    }
+
+    // Measures are counted in ticks, so time signatures stay on their measure
+    fn moves_measures(&self) -> bool {
+        false
+    }
+}
+
+/// Adds or edits the BPM change at `tick` and moves everything after it to the closest
+/// tick that keeps its time in milliseconds.
+pub fn set_bpm_keeping_time(chart: &mut Chart, tick: u32, bpm: f64) -> Result<()> {
+    if bpm <= 0.0 {
+        bail!("The BPM must be above 0");
+    }
+    let resolution = chart.beat.resolution;
+    let old_bpm = chart.beat.bpm.clone();
+    let mut edited_bpm = old_bpm.clone();
+    match edited_bpm.binary_search_by_key(&tick, |b| b.0) {
+        Ok(index) => edited_bpm[index].1 = bpm,
+        Err(index) => edited_bpm.insert(index, (tick, bpm)),
+    }
+
+    // Each later change depends on the ones before it
+    let mut new_bpm: Vec<(u32, f64)> = Vec::new();
+    for &(change_tick, value) in &edited_bpm {
+        let new_tick = if change_tick <= tick {
+            change_tick
+        } else {
+            let ms = tick_to_ms(&old_bpm, resolution, change_tick);
+            ms_to_tick(&new_bpm, resolution, ms).round() as u32
+        };
+        new_bpm.push((new_tick, value));
+    }
+
+    let mut edited = chart.clone();
+    edited.beat.bpm = edited_bpm;
+    apply_mapping(
+        &mut edited,
+        &Retime {
+            tick,
+            resolution,
+            old_bpm,
+            new_bpm,
+        },
+        resolution,
+    )?;
+    *chart = edited;
+    Ok(())
+}
+
+/// Notes and laser points after `tick` that retiming `before` into `after` moved off the
+/// `snap` grid, objects that were already off it are left out. Objects are matched by
+/// their order, which retiming keeps.
+pub fn off_grid(before: &Chart, after: &Chart, tick: u32, snap: SnapDivision) -> Vec<ObjectId> {
+    let resolution = after.beat.resolution;
+    let off = |t: u32| t > tick && snap.snap(t, resolution) != t;
+    let note_off = |n: &Interval| off(n.y) || off(n.y + n.l);
+    let mut objects = Vec::new();
+
+    for &fx in [false, true].iter() {
+        let (old_lanes, new_lanes) = if fx {
+            (&before.note.fx[..], &after.note.fx[..])
+        } else {
+            (&before.note.bt[..], &after.note.bt[..])
+        };
+        for (lane, (old, new)) in old_lanes.iter().zip(new_lanes).enumerate() {
+            objects.extend(
+                old.iter()
+                    .zip(new)
+                    .filter(|(o, n)| !note_off(o) && note_off(n))
+                    .map(|(_, n)| ObjectId::Interval { fx, lane, y: n.y }),
+            );
+        }
+    }
+
+    for (side, (old, new)) in before.note.laser.iter().zip(&after.note.laser).enumerate() {
+        for (old_section, section) in old.iter().zip(new) {
+            objects.extend(
+                old_section
+                    .1
+                    .iter()
+                    .zip(&section.1)
+                    .filter(|(o, n)| !off(old_section.0 + o.ry) && off(section.0 + n.ry))
+                    .map(|(_, n)| ObjectId::LaserPoint {
+                        side,
+                        section: section.0,
+                        ry: n.ry,
+                    }),
+            );
+        }
+    }
+
+    objects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart_edit::tests::{camera_point, chart, notes, section, sections, time_sigs};
+
+    fn mapping(transform: Transform, start: u32, end: u32) -> Mapping {
+        Mapping {
+            transform,
+            start,
+            end,
+        }
+    }
+
+    fn camera_ticks(chart: &Chart) -> Vec<u32> {
+        camera_graph(chart, CameraPaths::Zoom)
+            .iter()
+            .map(|p| p.y)
+            .collect()
+    }
+
+    #[test]
+    fn shift_mapping() {
+        let shift = mapping(Transform::Shift(100), 240, 480);
+
+        assert_eq!(shift.tick(0, 100).unwrap(), 100);
+        assert_eq!(shift.tick(240, 240).unwrap(), 340);
+        assert_eq!(shift.tick(480, 600).unwrap(), 700);
+        assert_eq!(shift.tick(600, 600).unwrap(), 600);
+        assert!(!shift.mirrors(240));
+
+        let back = mapping(Transform::Shift(-100), 0, 480);
+        assert_eq!(back.tick(100, 100).unwrap(), 0);
+        assert!(back.tick(50, 50).is_err());
+    }
+
+    #[test]
+    fn scale_mapping() {
+        let double = mapping(
+            Transform::Scale {
+                numerator: 2,
+                denominator: 1,
+            },
+            240,
+            480,
+        );
+
+        assert_eq!(double.tick(0, 100).unwrap(), 100);
+        assert_eq!(double.tick(240, 240).unwrap(), 240);
+        assert_eq!(double.tick(240, 360).unwrap(), 480);
+        assert_eq!(double.tick(480, 480).unwrap(), 720);
+        assert_eq!(double.tick(600, 600).unwrap(), 840);
+
+        let third = mapping(
+            Transform::Scale {
+                numerator: 1,
+                denominator: 3,
+            },
+            0,
+            u32::MAX,
+        );
+        assert_eq!(third.tick(0, 100).unwrap(), 33);
+        assert_eq!(third.tick(0, 101).unwrap(), 34);
+    }
+
+    #[test]
+    fn mirror_mapping() {
+        let mirror = mapping(Transform::Mirror, 240, 480);
+
+        assert!(!mirror.mirrors(0));
+        assert!(mirror.mirrors(240));
+        assert!(mirror.mirrors(480));
+        assert!(!mirror.mirrors(481));
+        assert_eq!(mirror.tick(240, 300).unwrap(), 300);
+    }
+
+    #[test]
+    fn mirror_range() {
+        let mut chart = chart();
+        chart.note.bt[0].push(Interval { y: 0, l: 0 });
+        chart.note.bt[1].push(Interval { y: 960, l: 0 });
+        chart.note.fx[0].push(Interval { y: 240, l: 240 });
+        chart.note.laser[0].push(section(0, &[(0, 0.0), (240, 0.25)]));
+
+        transform_chart(&mut chart, Some((0, 480)), Transform::Mirror).unwrap();
+
+        assert!(notes(&chart, false, 0).is_empty());
+        assert_eq!(notes(&chart, false, 3), vec![(0, 0)]);
+        assert_eq!(notes(&chart, false, 1), vec![(960, 0)]);
+        assert_eq!(notes(&chart, true, 1), vec![(240, 240)]);
+        assert!(chart.note.laser[0].is_empty());
+        assert_eq!(sections(&chart, 1), vec![(0, vec![(0, 1.0), (240, 0.75)])]);
+    }
+
+    #[test]
+    fn scale_range() {
+        let mut chart = chart();
+        chart.note.bt[0].push(Interval { y: 240, l: 240 });
+        chart.note.bt[0].push(Interval { y: 960, l: 0 });
+        chart.beat.bpm.push((960, 180.0));
+        chart.camera.cam.body.zoom.push(camera_point(960, 1.0));
+
+        let scale = Transform::Scale {
+            numerator: 2,
+            denominator: 1,
+        };
+        transform_chart(&mut chart, Some((240, 480)), scale).unwrap();
+
+        assert_eq!(notes(&chart, false, 0), vec![(240, 480), (1200, 0)]);
+        assert_eq!(chart.beat.bpm, vec![(0, 120.0), (1200, 180.0)]);
+        assert_eq!(camera_ticks(&chart), vec![1200]);
+    }
+
+    #[test]
+    fn failed_transform_leaves_the_chart_alone() {
+        let mut chart = chart();
+        chart.note.bt[0].push(Interval { y: 0, l: 0 });
+        chart.note.bt[0].push(Interval { y: 240, l: 0 });
+        chart.beat.time_sig.push((1, TimeSignature(3, 4)));
+
+        assert!(transform_chart(&mut chart, Some((0, 0)), Transform::Shift(240)).is_err());
+        assert!(transform_chart(&mut chart, Some((960, 960)), Transform::Shift(100)).is_err());
+        let zero = Transform::Scale {
+            numerator: 1,
+            denominator: 0,
+        };
+        assert!(transform_chart(&mut chart, None, zero).is_err());
+
+        assert_eq!(notes(&chart, false, 0), vec![(0, 0), (240, 0)]);
+        assert_eq!(time_sigs(&chart), vec![(0, 4, 4), (1, 3, 4)]);
+    }
+
+    #[test]
+    fn change_resolution_rescales_everything() {
+        let mut chart = chart();
+        chart.note.bt[2].push(Interval { y: 120, l: 360 });
+        chart.note.laser[1].push(section(240, &[(0, 0.0), (120, 1.0)]));
+        chart.beat.bpm.push((960, 180.0));
+        chart.beat.time_sig.push((1, TimeSignature(3, 4)));
+        chart.camera.cam.body.zoom.push(camera_point(480, 1.0));
+
+        assert!(resolution_report(&chart, 480).is_empty());
+        change_resolution(&mut chart, 480).unwrap();
+
+        assert_eq!(chart.beat.resolution, 480);
+        assert_eq!(notes(&chart, false, 2), vec![(240, 720)]);
+        assert_eq!(sections(&chart, 1), vec![(480, vec![(0, 0.0), (240, 1.0)])]);
+        assert_eq!(chart.beat.bpm, vec![(0, 120.0), (1920, 180.0)]);
+        assert_eq!(time_sigs(&chart), vec![(0, 4, 4), (1, 3, 4)]);
+        assert_eq!(camera_ticks(&chart), vec![960]);
+        assert!(change_resolution(&mut chart, 0).is_err());
+    }
+
+    #[test]
+    fn change_resolution_rounds_inexact_ticks() {
+        let mut chart = chart();
+        chart.note.bt[0].push(Interval { y: 1, l: 0 });
+        chart.note.bt[0].push(Interval { y: 480, l: 3 });
+        chart.camera.cam.body.zoom.push(camera_point(7, 1.0));
+
+        let report = resolution_report(&chart, 100);
+        assert_eq!(report.objects.len(), 2);
+        assert_eq!(report.camera_points, 1);
+        assert!(!report.has_inexact_time_signatures());
+
+        change_resolution(&mut chart, 100).unwrap();
+        assert_eq!(notes(&chart, false, 0), vec![(0, 0), (200, 1)]);
+        assert_eq!(camera_ticks(&chart), vec![3]);
+    }
+
+    #[test]
+    fn change_resolution_fails_on_inexact_time_signatures() {
+        let mut chart = chart();
+        chart.beat.time_sig.push((1, TimeSignature(7, 64)));
+        chart.beat.time_sig.push((2, TimeSignature(4, 4)));
+
+        let report = resolution_report(&chart, 100);
+        assert!(report.has_inexact_time_signatures());
+        assert_eq!(report.objects, vec![ObjectId::TimeSig { measure: 1 }]);
+        assert!(change_resolution(&mut chart, 100).is_err());
+        assert_eq!(chart.beat.resolution, 240);
+    }
+
+    #[test]
+    fn insert_measures_moves_later_objects() {
+        let mut chart = chart();
+        chart.note.bt[0].push(Interval { y: 0, l: 1920 });
+        chart.note.bt[1].push(Interval { y: 960, l: 0 });
+        chart.note.laser[0].push(section(480, &[(0, 0.0), (960, 1.0)]));
+        chart.beat.bpm.push((960, 180.0));
+        chart.beat.time_sig.push((2, TimeSignature(3, 4)));
+        chart.camera.cam.body.zoom.push(camera_point(960, 1.0));
+
+        insert_measures(&mut chart, 1, 2).unwrap();
+
+        assert_eq!(notes(&chart, false, 0), vec![(0, 3840)]);
+        assert_eq!(notes(&chart, false, 1), vec![(2880, 0)]);
+        assert_eq!(
+            sections(&chart, 0),
+            vec![(480, vec![(0, 0.0), (2880, 1.0)])]
+        );
+        assert_eq!(chart.beat.bpm, vec![(0, 120.0), (960, 180.0)]);
+        assert_eq!(time_sigs(&chart), vec![(0, 4, 4), (4, 3, 4)]);
+        assert_eq!(camera_ticks(&chart), vec![2880]);
+        assert!(insert_measures(&mut chart, 1, 0).is_err());
+    }
+
+    #[test]
+    fn delete_measures_removes_their_contents() {
+        let mut chart = chart();
+        chart.note.bt[0].push(Interval { y: 0, l: 0 });
+        chart.note.bt[0].push(Interval { y: 960, l: 0 });
+        chart.note.bt[0].push(Interval { y: 1920, l: 0 });
+        chart.note.bt[1].push(Interval { y: 480, l: 960 });
+        chart.note.laser[0].push(section(480, &[(0, 0.0), (720, 0.5), (1440, 1.0)]));
+        chart.note.laser[1].push(section(1000, &[(0, 0.0), (200, 1.0)]));
+        chart.beat.bpm.push((1200, 180.0));
+        chart.camera.cam.body.zoom.push(camera_point(1200, 1.0));
+        chart.camera.cam.body.zoom.push(camera_point(2000, 1.0));
+
+        delete_measures(&mut chart, 1, 1).unwrap();
+
+        assert_eq!(notes(&chart, false, 0), vec![(0, 0), (960, 0)]);
+        assert_eq!(notes(&chart, false, 1), vec![(480, 479)]);
+        assert_eq!(sections(&chart, 0), vec![(480, vec![(0, 0.0), (480, 1.0)])]);
+        assert!(chart.note.laser[1].is_empty());
+        assert_eq!(chart.beat.bpm, vec![(0, 120.0), (960, 180.0)]);
+        assert_eq!(camera_ticks(&chart), vec![1040]);
+        assert!(delete_measures(&mut chart, 1, 0).is_err());
+    }
+
+    #[test]
+    fn delete_measures_keeps_the_following_time_signature() {
+        let mut chart = chart();
+        chart.beat.time_sig.push((1, TimeSignature(3, 4)));
+        chart.note.bt[0].push(Interval { y: 1680, l: 0 });
+
+        delete_measures(&mut chart, 1, 1).unwrap();
+
+        assert_eq!(time_sigs(&chart), vec![(0, 4, 4), (1, 3, 4)]);
+        assert_eq!(notes(&chart, false, 0), vec![(960, 0)]);
+    }
+
+    #[test]
+    fn set_bpm_keeping_time_moves_later_objects() {
+        let mut chart = chart();
+        chart.beat.bpm.push((960, 60.0));
+        chart.note.bt[0].push(Interval { y: 1920, l: 0 });
+
+        set_bpm_keeping_time(&mut chart, 0, 240.0).unwrap();
+
+        assert_eq!(chart.beat.bpm, vec![(0, 240.0), (1920, 60.0)]);
+        assert_eq!(notes(&chart, false, 0), vec![(2880, 0)]);
+        assert!(set_bpm_keeping_time(&mut chart, 0, 0.0).is_err());
+    }
+}
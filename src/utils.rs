@@ -1,3 +1,9 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
 pub trait Overlaps {
     fn overlaps(&self, other: &Self) -> bool;
     fn contains(&self, y: u32) -> bool;
@@ -32,3 +38,24 @@ impl Overlaps for kson::LaserSection {
         }
     }
 }
+
+/// Writes `contents` to a temporary file next to `path` and renames it over `path`, so that
+/// the file is never left partially written.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut temp_path = path.as_os_str().to_os_string();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+pub fn read_kson(path: &Path) -> Result<kson::Chart> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}